@@ -0,0 +1,479 @@
+//! GPU メモリの割り当て
+//!
+//! `vkAllocateMemory` は回数に上限 (`maxMemoryAllocationCount`) があり遅いので、
+//! 大きなブロックをまとめて確保し、その中を切り分けてリソースに割り当てる
+
+use anyhow::Context;
+use ash::{
+    version::{DeviceV1_0, InstanceV1_0},
+    vk::{
+        Buffer, DeviceMemory, Image, MappedMemoryRange, MemoryAllocateInfo, MemoryMapFlags,
+        MemoryPropertyFlags, MemoryRequirements, PhysicalDevice, PhysicalDeviceMemoryProperties,
+        WHOLE_SIZE,
+    },
+    Device, Instance,
+};
+use std::{ptr::NonNull, sync::Mutex};
+
+/// 1 ブロックの大きさ (ヒープが小さい場合はその 1/8 まで小さくする)
+const BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// リソースの種類
+///
+/// 種類の違うリソースが `bufferImageGranularity` の範囲内で隣り合ってはいけない
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceKind {
+    /// バッファとリニアなイメージ
+    Linear,
+    /// 最適化された (タイリングが `OPTIMAL` の) イメージ
+    Optimal,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct UsedRange {
+    offset: u64,
+    size: u64,
+    kind: ResourceKind,
+}
+
+/// 1 つのメモリブロックの中で使われている範囲を管理する (GPU には触らない)
+#[derive(Debug)]
+pub struct SubAllocator {
+    size: u64,
+    granularity: u64,
+    /// オフセット順に並んでいる
+    used: Vec<UsedRange>,
+}
+
+impl SubAllocator {
+    pub fn new(size: u64, granularity: u64) -> SubAllocator {
+        SubAllocator {
+            size,
+            granularity: granularity.max(1),
+            used: Vec::new(),
+        }
+    }
+
+    /// 空いている範囲のうち、最初に収まる場所を探して確保し、そのオフセットを返す
+    pub fn allocate(&mut self, size: u64, alignment: u64, kind: ResourceKind) -> Option<u64> {
+        let alignment = alignment.max(1);
+        for index in 0..=self.used.len() {
+            let previous = index.checked_sub(1).map(|index| self.used[index]);
+            let next = self.used.get(index).copied();
+            let gap_start = previous.map_or(0, |range| range.offset + range.size);
+            let gap_end = next.map_or(self.size, |range| range.offset);
+            let mut offset = align_up(gap_start, alignment);
+            if let Some(previous) = previous {
+                if previous.kind != kind
+                    && same_page(
+                        previous.offset + previous.size - 1,
+                        offset,
+                        self.granularity,
+                    )
+                {
+                    offset = align_up(offset, self.granularity);
+                }
+            }
+            let end = offset + size;
+            if end > gap_end {
+                continue;
+            }
+            if let Some(next) = next {
+                if next.kind != kind && same_page(end - 1, next.offset, self.granularity) {
+                    continue;
+                }
+            }
+            self.used.insert(index, UsedRange { offset, size, kind });
+            return Some(offset);
+        }
+        None
+    }
+
+    /// `allocate` が返したオフセットの範囲を解放する
+    pub fn free(&mut self, offset: u64) {
+        match self.used.iter().position(|range| range.offset == offset) {
+            Some(index) => {
+                self.used.remove(index);
+            }
+            None => error!("Tried to free unknown memory range at offset {}", offset),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.used.is_empty()
+    }
+
+    /// 使われているバイト数の合計
+    pub fn used_size(&self) -> u64 {
+        self.used.iter().map(|range| range.size).sum()
+    }
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    match value % alignment {
+        0 => value,
+        remainder => value + alignment - remainder,
+    }
+}
+
+fn align_down(value: u64, alignment: u64) -> u64 {
+    value - value % alignment
+}
+
+fn same_page(a: u64, b: u64, page_size: u64) -> bool {
+    a / page_size == b / page_size
+}
+
+struct MemoryBlock {
+    id: u64,
+    memory: DeviceMemory,
+    memory_type_index: u32,
+    /// `HOST_VISIBLE` なブロックは作成時にまとめてマップしておく
+    mapped: Option<NonNull<u8>>,
+    /// `HOST_COHERENT` でなければ、GPU の書き込みを読む前に無効化しなければならない
+    coherent: bool,
+    size: u64,
+    /// 大きなリソースのために専用で確保したブロックは、空になったらすぐに解放する
+    dedicated: bool,
+    sub_allocator: SubAllocator,
+}
+
+struct AllocatorState {
+    blocks: Vec<MemoryBlock>,
+    next_block_id: u64,
+}
+
+/// 論理デバイスごとに 1 つ作る、GPU メモリのアロケータ
+///
+/// 論理デバイスを破棄する前に `destroy` を呼ばなければならない
+pub struct MemoryAllocator {
+    device: Device,
+    memory_properties: PhysicalDeviceMemoryProperties,
+    buffer_image_granularity: u64,
+    non_coherent_atom_size: u64,
+    max_allocation_count: u32,
+    state: Mutex<AllocatorState>,
+}
+
+// マップしたポインタはブロックと一緒に Mutex で守っている
+unsafe impl Send for MemoryAllocator {}
+unsafe impl Sync for MemoryAllocator {}
+
+impl MemoryAllocator {
+    pub fn new(
+        instance: &Instance,
+        physical_device: &PhysicalDevice,
+        device: Device,
+    ) -> MemoryAllocator {
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(*physical_device) };
+        let limits = unsafe { instance.get_physical_device_properties(*physical_device) }.limits;
+        MemoryAllocator {
+            device,
+            memory_properties,
+            buffer_image_granularity: limits.buffer_image_granularity,
+            non_coherent_atom_size: limits.non_coherent_atom_size.max(1),
+            max_allocation_count: limits.max_memory_allocation_count,
+            state: Mutex::new(AllocatorState {
+                blocks: Vec::new(),
+                next_block_id: 0,
+            }),
+        }
+    }
+
+    /// `required` をすべて満たすメモリタイプのうち、`preferred` も満たすものを優先して選ぶ
+    pub fn find_memory_type(
+        &self,
+        memory_type_bits: u32,
+        required: MemoryPropertyFlags,
+        preferred: MemoryPropertyFlags,
+    ) -> Option<u32> {
+        let memory_types = &self.memory_properties.memory_types
+            [..self.memory_properties.memory_type_count as usize];
+        let find = |flags: MemoryPropertyFlags| {
+            memory_types
+                .iter()
+                .enumerate()
+                .find_map(|(index, memory_type)| {
+                    (memory_type_bits & (1 << index) != 0
+                        && memory_type.property_flags.contains(flags))
+                    .then(|| index as u32)
+                })
+        };
+        find(required | preferred).or_else(|| find(required))
+    }
+
+    /// イメージ用のメモリを割り当ててバインドする
+    pub fn allocate_for_image(
+        &self,
+        image: Image,
+        kind: ResourceKind,
+        required: MemoryPropertyFlags,
+        preferred: MemoryPropertyFlags,
+    ) -> anyhow::Result<Allocation> {
+        let requirements = unsafe { self.device.get_image_memory_requirements(image) };
+        let allocation = self.allocate(requirements, kind, required, preferred)?;
+        unsafe {
+            self.device
+                .bind_image_memory(image, allocation.memory, allocation.offset)
+        }
+        .context("Failed to bind memory to image")?;
+        Ok(allocation)
+    }
+
+    /// バッファ用のメモリを割り当ててバインドする
+    pub fn allocate_for_buffer(
+        &self,
+        buffer: Buffer,
+        required: MemoryPropertyFlags,
+        preferred: MemoryPropertyFlags,
+    ) -> anyhow::Result<Allocation> {
+        let requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
+        let allocation = self.allocate(requirements, ResourceKind::Linear, required, preferred)?;
+        unsafe {
+            self.device
+                .bind_buffer_memory(buffer, allocation.memory, allocation.offset)
+        }
+        .context("Failed to bind memory to buffer")?;
+        Ok(allocation)
+    }
+
+    pub fn allocate(
+        &self,
+        requirements: MemoryRequirements,
+        kind: ResourceKind,
+        required: MemoryPropertyFlags,
+        preferred: MemoryPropertyFlags,
+    ) -> anyhow::Result<Allocation> {
+        let memory_type_index = self
+            .find_memory_type(requirements.memory_type_bits, required, preferred)
+            .with_context(|| format!("No memory type satisfies {:?}", required))?;
+        // コヒーレントでないメモリは `nonCoherentAtomSize` 単位で無効化するので、
+        // 隣の割り当てと同じ単位に入らないように揃えておく
+        let requirements = if self.is_non_coherent(memory_type_index) {
+            MemoryRequirements {
+                size: align_up(requirements.size, self.non_coherent_atom_size),
+                alignment: requirements.alignment.max(self.non_coherent_atom_size),
+                ..requirements
+            }
+        } else {
+            requirements
+        };
+        let mut state = self.state.lock().unwrap();
+        for block in state
+            .blocks
+            .iter_mut()
+            .filter(|block| block.memory_type_index == memory_type_index && !block.dedicated)
+        {
+            if let Some(offset) =
+                block
+                    .sub_allocator
+                    .allocate(requirements.size, requirements.alignment, kind)
+            {
+                return Ok(Allocation::new(self, block, offset, requirements.size));
+            }
+        }
+
+        let block_size = self.block_size(memory_type_index);
+        let dedicated = requirements.size > block_size / 2;
+        let block_size = if dedicated {
+            requirements.size
+        } else {
+            block_size
+        };
+        let block = self.create_block(&mut state, memory_type_index, block_size, dedicated)?;
+        let offset = block
+            .sub_allocator
+            .allocate(requirements.size, requirements.alignment, kind)
+            .context("Allocation does not fit in a new memory block")?;
+        Ok(Allocation::new(self, block, offset, requirements.size))
+    }
+
+    /// すべてのブロックを解放する
+    pub fn destroy(&self) {
+        let mut state = self.state.lock().unwrap();
+        for block in state.blocks.drain(..) {
+            if !block.sub_allocator.is_empty() {
+                warn!(
+                    "Memory block #{} still has {} bytes in use",
+                    block.id,
+                    block.sub_allocator.used_size()
+                );
+            }
+            unsafe { self.device.free_memory(block.memory, None) };
+        }
+        trace!("All GPU memory blocks were released");
+    }
+
+    fn is_non_coherent(&self, memory_type_index: u32) -> bool {
+        let flags = self.memory_properties.memory_types[memory_type_index as usize].property_flags;
+        flags.contains(MemoryPropertyFlags::HOST_VISIBLE)
+            && !flags.contains(MemoryPropertyFlags::HOST_COHERENT)
+    }
+
+    fn block_size(&self, memory_type_index: u32) -> u64 {
+        let heap_index = self.memory_properties.memory_types[memory_type_index as usize].heap_index;
+        let heap_size = self.memory_properties.memory_heaps[heap_index as usize].size;
+        BLOCK_SIZE.min(heap_size / 8)
+    }
+
+    fn create_block<'s>(
+        &self,
+        state: &'s mut AllocatorState,
+        memory_type_index: u32,
+        size: u64,
+        dedicated: bool,
+    ) -> anyhow::Result<&'s mut MemoryBlock> {
+        ensure!(
+            (state.blocks.len() as u32) < self.max_allocation_count,
+            "Reached maxMemoryAllocationCount ({})",
+            self.max_allocation_count
+        );
+        let memory = unsafe {
+            self.device.allocate_memory(
+                &MemoryAllocateInfo::builder()
+                    .allocation_size(size)
+                    .memory_type_index(memory_type_index)
+                    .build(),
+                None,
+            )
+        }
+        .with_context(|| format!("Failed to allocate {} bytes of GPU memory", size))?;
+        let host_visible = self.memory_properties.memory_types[memory_type_index as usize]
+            .property_flags
+            .contains(MemoryPropertyFlags::HOST_VISIBLE);
+        let mapped = if host_visible {
+            match unsafe {
+                self.device
+                    .map_memory(memory, 0, WHOLE_SIZE, MemoryMapFlags::empty())
+            } {
+                Ok(pointer) => NonNull::new(pointer as *mut u8),
+                Err(err) => {
+                    unsafe { self.device.free_memory(memory, None) };
+                    return Err(err).context("Failed to map memory block");
+                }
+            }
+        } else {
+            None
+        };
+        let id = state.next_block_id;
+        state.next_block_id += 1;
+        debug!(
+            "Memory block #{} was allocated (type {}, {} bytes{})",
+            id,
+            memory_type_index,
+            size,
+            if dedicated { ", dedicated" } else { "" }
+        );
+        state.blocks.push(MemoryBlock {
+            id,
+            memory,
+            memory_type_index,
+            mapped,
+            coherent: !self.is_non_coherent(memory_type_index),
+            size,
+            dedicated,
+            sub_allocator: SubAllocator::new(size, self.buffer_image_granularity),
+        });
+        Ok(state.blocks.last_mut().unwrap())
+    }
+
+    fn free(&self, block_id: u64, offset: u64) {
+        let mut state = self.state.lock().unwrap();
+        let index = match state.blocks.iter().position(|block| block.id == block_id) {
+            Some(index) => index,
+            None => {
+                error!("Tried to free memory of unknown block #{}", block_id);
+                return;
+            }
+        };
+        let block = &mut state.blocks[index];
+        block.sub_allocator.free(offset);
+        if block.dedicated && block.sub_allocator.is_empty() {
+            let block = state.blocks.remove(index);
+            unsafe { self.device.free_memory(block.memory, None) };
+            debug!("Memory block #{} was released", block.id);
+        }
+    }
+}
+
+/// 自動で解放される、メモリブロックの一部
+pub struct Allocation<'a> {
+    allocator: &'a MemoryAllocator,
+    block_id: u64,
+    memory: DeviceMemory,
+    offset: u64,
+    size: u64,
+    mapped: Option<NonNull<u8>>,
+    coherent: bool,
+    block_size: u64,
+}
+
+impl<'a> Allocation<'a> {
+    fn new(
+        allocator: &'a MemoryAllocator,
+        block: &MemoryBlock,
+        offset: u64,
+        size: u64,
+    ) -> Allocation<'a> {
+        Allocation {
+            allocator,
+            block_id: block.id,
+            memory: block.memory,
+            offset,
+            size,
+            mapped: block.mapped.map(|pointer| unsafe {
+                NonNull::new_unchecked(pointer.as_ptr().add(offset as usize))
+            }),
+            coherent: block.coherent,
+            block_size: block.size,
+        }
+    }
+
+    pub fn get_memory_raw(&self) -> DeviceMemory {
+        self.memory
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    /// `HOST_VISIBLE` なメモリなら、この割り当ての先頭を指すポインタを返す
+    pub fn get_mapped_ptr(&self) -> Option<*mut u8> {
+        self.mapped.map(NonNull::as_ptr)
+    }
+
+    /// GPU が書き込んだ内容を CPU から読めるように、マップした範囲のキャッシュを無効化する
+    ///
+    /// コヒーレントなメモリやマップされていないメモリでは何もしない
+    pub fn invalidate(&self) -> anyhow::Result<()> {
+        if self.coherent || self.mapped.is_none() {
+            return Ok(());
+        }
+        let atom_size = self.allocator.non_coherent_atom_size;
+        let start = align_down(self.offset, atom_size);
+        let end = align_up(self.offset + self.size, atom_size).min(self.block_size);
+        let range = MappedMemoryRange::builder()
+            .memory(self.memory)
+            .offset(start)
+            .size(end - start)
+            .build();
+        unsafe {
+            self.allocator
+                .device
+                .invalidate_mapped_memory_ranges(&[range])
+        }
+        .context("Failed to invalidate mapped memory range")
+    }
+}
+
+impl Drop for Allocation<'_> {
+    fn drop(&mut self) {
+        self.allocator.free(self.block_id, self.offset);
+        trace!("GPU memory range was released");
+    }
+}
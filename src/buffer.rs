@@ -0,0 +1,154 @@
+//! GPU バッファ
+
+use crate::allocator::{Allocation, MemoryAllocator};
+use anyhow::Context;
+use ash::{
+    version::DeviceV1_0,
+    vk::{Buffer, BufferCreateInfo, BufferUsageFlags, MemoryPropertyFlags, SharingMode},
+    Device,
+};
+use bytemuck::Pod;
+use std::{mem::size_of_val, ptr::copy_nonoverlapping};
+
+/// バッファをどのメモリに置くか
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryLocation {
+    /// GPU からしか触らない (頂点バッファやインデックスバッファなど)
+    GpuOnly,
+    /// CPU から書き込んで GPU が読む (ステージングバッファやユニフォームバッファなど)
+    CpuToGpu,
+    /// GPU が書き込んで CPU が読む
+    GpuToCpu,
+}
+
+impl MemoryLocation {
+    /// 必須のフラグと、できれば欲しいフラグ
+    fn memory_property_flags(self) -> (MemoryPropertyFlags, MemoryPropertyFlags) {
+        match self {
+            MemoryLocation::GpuOnly => (
+                MemoryPropertyFlags::DEVICE_LOCAL,
+                MemoryPropertyFlags::empty(),
+            ),
+            // フラッシュしなくて済むように、コヒーレントなメモリに限る
+            MemoryLocation::CpuToGpu => (
+                MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+                MemoryPropertyFlags::empty(),
+            ),
+            // 読み出しが速いキャッシュ付きのメモリはコヒーレントでないことが多いので、
+            // コヒーレントは求めずに読む前に無効化する
+            MemoryLocation::GpuToCpu => (
+                MemoryPropertyFlags::HOST_VISIBLE,
+                MemoryPropertyFlags::HOST_CACHED,
+            ),
+        }
+    }
+}
+
+/// 自動で解放される、GPU バッファのラッパー
+pub struct ManagedBuffer<'a> {
+    device: &'a Device,
+    allocation: Allocation<'a>,
+    buffer_raw: Buffer,
+    size: u64,
+}
+
+impl<'a> ManagedBuffer<'a> {
+    pub fn new(
+        device: &'a Device,
+        allocator: &'a MemoryAllocator,
+        size: u64,
+        usage: BufferUsageFlags,
+        location: MemoryLocation,
+    ) -> anyhow::Result<ManagedBuffer<'a>> {
+        ensure!(size > 0, "Buffer size must not be zero");
+        let create_info = BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(SharingMode::EXCLUSIVE)
+            .build();
+        let buffer_raw = unsafe { device.create_buffer(&create_info, None) }
+            .context("Failed to create buffer")?;
+        let (required, preferred) = location.memory_property_flags();
+        let allocation = match allocator.allocate_for_buffer(buffer_raw, required, preferred) {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                unsafe { device.destroy_buffer(buffer_raw, None) };
+                return Err(err).context("Failed to allocate memory for buffer");
+            }
+        };
+        Ok(ManagedBuffer {
+            device,
+            allocation,
+            buffer_raw,
+            size,
+        })
+    }
+
+    pub fn get_buffer_raw(&self) -> Buffer {
+        self.buffer_raw
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    /// CPU から見えるバッファなら、その先頭を指すポインタを返す
+    pub fn get_mapped_ptr(&self) -> Option<*mut u8> {
+        self.allocation.get_mapped_ptr()
+    }
+
+    /// CPU から見えるバッファの先頭に `data` を書き込む
+    pub fn write<T>(&self, data: &[T]) -> anyhow::Result<()>
+    where
+        T: Copy,
+    {
+        let byte_size = size_of_val(data);
+        ensure!(
+            byte_size as u64 <= self.size,
+            "Data ({} bytes) does not fit in buffer ({} bytes)",
+            byte_size,
+            self.size
+        );
+        let mapped = self
+            .get_mapped_ptr()
+            .context("Buffer is not visible from the host")?;
+        unsafe { copy_nonoverlapping(data.as_ptr() as *const u8, mapped, byte_size) };
+        Ok(())
+    }
+
+    /// CPU から見えるバッファの先頭から `T` を `count` 個読み出す
+    ///
+    /// GPU の書き込みが終わり、`HOST_READ` へのバリアを通ったあとで呼ばなければならない
+    pub fn read<T>(&self, count: usize) -> anyhow::Result<Vec<T>>
+    where
+        T: Pod,
+    {
+        let mut data = vec![T::zeroed(); count];
+        let byte_size = size_of_val(data.as_slice());
+        ensure!(
+            byte_size as u64 <= self.size,
+            "Reading {} bytes exceeds buffer ({} bytes)",
+            byte_size,
+            self.size
+        );
+        let mapped = self
+            .get_mapped_ptr()
+            .context("Buffer is not visible from the host")?;
+        self.allocation.invalidate()?;
+        unsafe {
+            copy_nonoverlapping(
+                mapped as *const u8,
+                bytemuck::cast_slice_mut::<T, u8>(&mut data).as_mut_ptr(),
+                byte_size,
+            )
+        };
+        Ok(data)
+    }
+}
+
+impl Drop for ManagedBuffer<'_> {
+    fn drop(&mut self) {
+        unsafe { self.device.destroy_buffer(self.buffer_raw, None) };
+        trace!("Buffer was destroyed");
+    }
+}
@@ -1,15 +1,28 @@
+use crate::{
+    buffer::ManagedBuffer,
+    framebuffer::ManagedFramebuffer,
+    linear_image::ManagedAndLinearImage,
+    mesh::Mesh,
+    optimized_image::ManagedAndOptimizedImage,
+    pipeline::{full_scissor, full_viewport, validate_push_constants, ManagedPipeline},
+    render_pass::ManagedRenderPass,
+};
+use anyhow::Context;
 use ash::{
     version::DeviceV1_0,
     vk::{
-        ClearColorValue, ClearValue, CommandBuffer, CommandBufferBeginInfo, CommandPool, Extent2D,
-        Fence, Offset2D, PipelineBindPoint, Queue, Rect2D, RenderPassBeginInfo, SubmitInfo,
-        SubpassContents,
+        AccessFlags, BufferCopy, BufferImageCopy, BufferMemoryBarrier, ClearColorValue, ClearValue,
+        CommandBuffer, CommandBufferBeginInfo, CommandBufferUsageFlags, CommandPool,
+        DependencyFlags, DescriptorSet, DynamicState, Extent2D, Extent3D, Fence, Image,
+        ImageAspectFlags, ImageCopy, ImageLayout, ImageMemoryBarrier, ImageSubresourceLayers,
+        ImageSubresourceRange, IndexType, Offset2D, Offset3D, PipelineBindPoint,
+        PipelineStageFlags, Queue, Rect2D, RenderPassBeginInfo, Semaphore, ShaderStageFlags,
+        SubmitInfo, SubpassContents, Viewport, QUEUE_FAMILY_IGNORED, WHOLE_SIZE,
     },
     Device,
 };
-use crate::{
-    framebuffer::ManagedFramebuffer, pipeline::ManagedPipeline, render_pass::ManagedRenderPass,
-};
+use bytemuck::Pod;
+use std::mem::size_of;
 
 pub struct ManagedCommandBuffer<'a> {
     device: &'a Device,
@@ -30,19 +43,95 @@ impl<'a> ManagedCommandBuffer<'a> {
         }
     }
 
-    pub fn draw_triangle(
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
         &self,
         queue: &Queue,
         render_pass: &ManagedRenderPass,
         framebuffer: &ManagedFramebuffer,
         pipeline: &ManagedPipeline,
+        vertex_buffer: &ManagedBuffer,
+        vertex_count: u32,
         width: u32,
         height: u32,
     ) -> anyhow::Result<()> {
+        self.record_draw(
+            render_pass,
+            framebuffer,
+            pipeline,
+            vertex_buffer,
+            vertex_count,
+            width,
+            height,
+        )?;
+        self.submit(queue, &[], &[], &[], Fence::null())?;
+        unsafe { self.device.queue_wait_idle(*queue) }?;
+        Ok(())
+    }
+
+    /// 頂点バッファの先頭から `vertex_count` 個の頂点を描画するコマンドを記録する (キューへの送信はしない)
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_draw(
+        &self,
+        render_pass: &ManagedRenderPass,
+        framebuffer: &ManagedFramebuffer,
+        pipeline: &ManagedPipeline,
+        vertex_buffer: &ManagedBuffer,
+        vertex_count: u32,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<()> {
+        self.record_render_pass(
+            render_pass,
+            framebuffer,
+            pipeline,
+            width,
+            height,
+            |commands| {
+                commands.bind_vertex_buffer(vertex_buffer);
+                commands.draw(vertex_count);
+                Ok(())
+            },
+        )
+    }
+
+    /// メッシュのすべてのサブメッシュを描画するコマンドを記録する (キューへの送信はしない)
+    pub fn record_draw_mesh(
+        &self,
+        render_pass: &ManagedRenderPass,
+        framebuffer: &ManagedFramebuffer,
+        pipeline: &ManagedPipeline,
+        mesh: &Mesh,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<()> {
+        self.record_render_pass(
+            render_pass,
+            framebuffer,
+            pipeline,
+            width,
+            height,
+            |commands| {
+                commands.draw_mesh(mesh);
+                Ok(())
+            },
+        )
+    }
+
+    /// レンダーパスを開始してパイプラインをバインドし、`draw` で描画コマンドを記録する (キューへの送信はしない)
+    pub fn record_render_pass<F>(
+        &self,
+        render_pass: &ManagedRenderPass,
+        framebuffer: &ManagedFramebuffer,
+        pipeline: &ManagedPipeline,
+        width: u32,
+        height: u32,
+        draw: F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnOnce(&RenderPassCommands) -> anyhow::Result<()>,
+    {
         let begin_info = CommandBufferBeginInfo::builder().build();
-        let submit_info = SubmitInfo::builder()
-            .command_buffers(&[self.command_buffer_raw])
-            .build();
         unsafe {
             self.device
                 .begin_command_buffer(self.command_buffer_raw, &begin_info)
@@ -73,15 +162,566 @@ impl<'a> ManagedCommandBuffer<'a> {
                 PipelineBindPoint::GRAPHICS,
                 pipeline.get_pipeline_raw(),
             );
-            self.device.cmd_draw(self.command_buffer_raw, 3, 1, 0, 0);
+        }
+        let commands = RenderPassCommands {
+            device: self.device,
+            command_buffer_raw: self.command_buffer_raw,
+            pipeline,
+        };
+        // 動的なビューポートとシザーは、描画範囲全体で初期化しておく
+        if pipeline.has_dynamic_state(DynamicState::VIEWPORT) {
+            commands.set_viewport(full_viewport(width, height))?;
+        }
+        if pipeline.has_dynamic_state(DynamicState::SCISSOR) {
+            commands.set_scissor(full_scissor(width, height))?;
+        }
+        // 記録に失敗しても、コマンドバッファを記録中のままにしない
+        let result = draw(&commands);
+        unsafe {
             self.device.cmd_end_render_pass(self.command_buffer_raw);
             self.device.end_command_buffer(self.command_buffer_raw)?;
+        }
+        result
+    }
+
+    /// 記録済みのコマンドをキューに送る
+    ///
+    /// `wait_semaphores` と `wait_stages` は同じ長さでなければならない。
+    /// 実行が終わると `signal_semaphores` と `fence` がシグナルされる
+    pub fn submit(
+        &self,
+        queue: &Queue,
+        wait_semaphores: &[Semaphore],
+        wait_stages: &[PipelineStageFlags],
+        signal_semaphores: &[Semaphore],
+        fence: Fence,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            wait_semaphores.len() == wait_stages.len(),
+            "wait_semaphores and wait_stages must have the same length"
+        );
+        let submit_info = SubmitInfo::builder()
+            .wait_semaphores(wait_semaphores)
+            .wait_dst_stage_mask(wait_stages)
+            .command_buffers(&[self.command_buffer_raw])
+            .signal_semaphores(signal_semaphores)
+            .build();
+        unsafe { self.device.queue_submit(*queue, &[submit_info], fence) }
+            .context("Failed to submit command buffer")?;
+        Ok(())
+    }
+
+    /// バッファの内容を別のバッファへコピーし、完了するまで待つ
+    pub fn copy_buffer(
+        &self,
+        queue: &Queue,
+        src: &ManagedBuffer,
+        dst: &ManagedBuffer,
+        size: u64,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            size <= src.get_size() && size <= dst.get_size(),
+            "Copy size ({} bytes) exceeds buffer size",
+            size
+        );
+        let begin_info = CommandBufferBeginInfo::builder()
+            .flags(CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+            .build();
+        let region = BufferCopy::builder()
+            .src_offset(0)
+            .dst_offset(0)
+            .size(size)
+            .build();
+        unsafe {
+            self.device
+                .begin_command_buffer(self.command_buffer_raw, &begin_info)?;
+            self.device.cmd_copy_buffer(
+                self.command_buffer_raw,
+                src.get_buffer_raw(),
+                dst.get_buffer_raw(),
+                &[region],
+            );
+            // CPU から見えるバッファなら、転送が終わってから CPU が読み出す
+            if dst.get_mapped_ptr().is_some() {
+                self.device.cmd_pipeline_barrier(
+                    self.command_buffer_raw,
+                    PipelineStageFlags::TRANSFER,
+                    PipelineStageFlags::HOST,
+                    DependencyFlags::empty(),
+                    &[],
+                    &[BufferMemoryBarrier::builder()
+                        .buffer(dst.get_buffer_raw())
+                        .offset(0)
+                        .size(size)
+                        .src_access_mask(AccessFlags::TRANSFER_WRITE)
+                        .dst_access_mask(AccessFlags::HOST_READ)
+                        .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+                        .build()],
+                    &[],
+                );
+            }
+            self.device.end_command_buffer(self.command_buffer_raw)?;
+        }
+        self.submit(queue, &[], &[], &[], Fence::null())?;
+        unsafe { self.device.queue_wait_idle(*queue) }?;
+        Ok(())
+    }
+
+    /// バッファに詰めて置いたピクセルをイメージへコピーし、シェーダから読めるレイアウトに遷移させる
+    pub fn copy_buffer_to_image(
+        &self,
+        queue: &Queue,
+        src: &ManagedBuffer,
+        dst: Image,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<()> {
+        let begin_info = CommandBufferBeginInfo::builder()
+            .flags(CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+            .build();
+        let region = BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(
+                ImageSubresourceLayers::builder()
+                    .aspect_mask(ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .build(),
+            )
+            .image_offset(Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(Extent3D {
+                width,
+                height,
+                depth: 1,
+            })
+            .build();
+        unsafe {
+            self.device
+                .begin_command_buffer(self.command_buffer_raw, &begin_info)?;
+            self.device.cmd_pipeline_barrier(
+                self.command_buffer_raw,
+                PipelineStageFlags::TOP_OF_PIPE,
+                PipelineStageFlags::TRANSFER,
+                DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_layout_barrier(
+                    dst,
+                    ImageLayout::UNDEFINED,
+                    ImageLayout::TRANSFER_DST_OPTIMAL,
+                    AccessFlags::empty(),
+                    AccessFlags::TRANSFER_WRITE,
+                )],
+            );
+            self.device.cmd_copy_buffer_to_image(
+                self.command_buffer_raw,
+                src.get_buffer_raw(),
+                dst,
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+            // 転送が終わってからフラグメントシェーダが読む
+            self.device.cmd_pipeline_barrier(
+                self.command_buffer_raw,
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::FRAGMENT_SHADER,
+                DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_layout_barrier(
+                    dst,
+                    ImageLayout::TRANSFER_DST_OPTIMAL,
+                    ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    AccessFlags::TRANSFER_WRITE,
+                    AccessFlags::SHADER_READ,
+                )],
+            );
+            self.device.end_command_buffer(self.command_buffer_raw)?;
+        }
+        self.submit(queue, &[], &[], &[], Fence::null())?;
+        unsafe { self.device.queue_wait_idle(*queue) }?;
+        Ok(())
+    }
+
+    /// `SHADER_READ_ONLY_OPTIMAL` レイアウトのイメージの内容を、CPU から読めるようにバッファへコピーする
+    ///
+    /// コピーが終わるとイメージは `SHADER_READ_ONLY_OPTIMAL` レイアウトに戻る
+    pub fn copy_image_to_buffer(
+        &self,
+        queue: &Queue,
+        src: Image,
+        dst: &ManagedBuffer,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<()> {
+        let begin_info = CommandBufferBeginInfo::builder()
+            .flags(CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+            .build();
+        let region = BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(
+                ImageSubresourceLayers::builder()
+                    .aspect_mask(ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .build(),
+            )
+            .image_offset(Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(Extent3D {
+                width,
+                height,
+                depth: 1,
+            })
+            .build();
+        unsafe {
+            self.device
+                .begin_command_buffer(self.command_buffer_raw, &begin_info)?;
+            self.device.cmd_pipeline_barrier(
+                self.command_buffer_raw,
+                PipelineStageFlags::FRAGMENT_SHADER,
+                PipelineStageFlags::TRANSFER,
+                DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_layout_barrier(
+                    src,
+                    ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    AccessFlags::empty(),
+                    AccessFlags::TRANSFER_READ,
+                )],
+            );
+            self.device.cmd_copy_image_to_buffer(
+                self.command_buffer_raw,
+                src,
+                ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst.get_buffer_raw(),
+                &[region],
+            );
+            // 転送が終わってから CPU が読み出し、フラグメントシェーダがまた読めるようにする
+            self.device.cmd_pipeline_barrier(
+                self.command_buffer_raw,
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::HOST | PipelineStageFlags::FRAGMENT_SHADER,
+                DependencyFlags::empty(),
+                &[],
+                &[BufferMemoryBarrier::builder()
+                    .buffer(dst.get_buffer_raw())
+                    .offset(0)
+                    .size(WHOLE_SIZE)
+                    .src_access_mask(AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(AccessFlags::HOST_READ)
+                    .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+                    .build()],
+                &[image_layout_barrier(
+                    src,
+                    ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    AccessFlags::empty(),
+                    AccessFlags::SHADER_READ,
+                )],
+            );
+            self.device.end_command_buffer(self.command_buffer_raw)?;
+        }
+        self.submit(queue, &[], &[], &[], Fence::null())?;
+        unsafe { self.device.queue_wait_idle(*queue) }?;
+        Ok(())
+    }
+
+    /// 描画結果を CPU から読み出せるように、最適化されたイメージの内容をリニアなイメージへコピーする
+    pub fn copy_to_linear_image(
+        &self,
+        queue: &Queue,
+        src: &ManagedAndOptimizedImage,
+        dst: &ManagedAndLinearImage,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<()> {
+        let begin_info = CommandBufferBeginInfo::builder().build();
+        let subresource_layers = ImageSubresourceLayers::builder()
+            .aspect_mask(ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1)
+            .build();
+        let region = ImageCopy::builder()
+            .src_subresource(subresource_layers)
+            .src_offset(Offset3D { x: 0, y: 0, z: 0 })
+            .dst_subresource(subresource_layers)
+            .dst_offset(Offset3D { x: 0, y: 0, z: 0 })
+            .extent(Extent3D {
+                width,
+                height,
+                depth: 1,
+            })
+            .build();
+        unsafe {
             self.device
-                .queue_submit(*queue, &[submit_info], Fence::null())?;
-            self.device.queue_wait_idle(*queue)?;
+                .begin_command_buffer(self.command_buffer_raw, &begin_info)
+        }?;
+        unsafe {
+            // レンダーパスの書き込みが終わってから転送を始める
+            self.device.cmd_pipeline_barrier(
+                self.command_buffer_raw,
+                PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                PipelineStageFlags::TRANSFER,
+                DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    image_layout_barrier(
+                        src.get_image_raw(),
+                        ImageLayout::GENERAL,
+                        ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        AccessFlags::COLOR_ATTACHMENT_WRITE,
+                        AccessFlags::TRANSFER_READ,
+                    ),
+                    image_layout_barrier(
+                        dst.get_image_raw(),
+                        ImageLayout::UNDEFINED,
+                        ImageLayout::TRANSFER_DST_OPTIMAL,
+                        AccessFlags::empty(),
+                        AccessFlags::TRANSFER_WRITE,
+                    ),
+                ],
+            );
+            self.device.cmd_copy_image(
+                self.command_buffer_raw,
+                src.get_image_raw(),
+                ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst.get_image_raw(),
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+            // 転送が終わってから CPU が読み出す
+            self.device.cmd_pipeline_barrier(
+                self.command_buffer_raw,
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::HOST,
+                DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_layout_barrier(
+                    dst.get_image_raw(),
+                    ImageLayout::TRANSFER_DST_OPTIMAL,
+                    ImageLayout::GENERAL,
+                    AccessFlags::TRANSFER_WRITE,
+                    AccessFlags::HOST_READ,
+                )],
+            );
+            self.device.end_command_buffer(self.command_buffer_raw)?;
         }
+        self.submit(queue, &[], &[], &[], Fence::null())?;
+        unsafe { self.device.queue_wait_idle(*queue) }?;
+        Ok(())
+    }
+}
+
+/// レンダーパスの中で、バインドしたパイプラインを使って描画コマンドを記録する
+pub struct RenderPassCommands<'a> {
+    device: &'a Device,
+    command_buffer_raw: CommandBuffer,
+    pipeline: &'a ManagedPipeline<'a>,
+}
+
+impl RenderPassCommands<'_> {
+    /// パイプラインのレイアウトの `first_set` 番目から順にディスクリプタセットをバインドする
+    pub fn bind_descriptor_sets(
+        &self,
+        first_set: u32,
+        descriptor_sets: &[DescriptorSet],
+    ) -> anyhow::Result<()> {
+        ensure!(
+            first_set as usize + descriptor_sets.len()
+                <= self.pipeline.get_set_layout_count() as usize,
+            "Pipeline layout has only {} descriptor sets, but tried to bind {} sets from set {}",
+            self.pipeline.get_set_layout_count(),
+            descriptor_sets.len(),
+            first_set
+        );
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(
+                self.command_buffer_raw,
+                PipelineBindPoint::GRAPHICS,
+                self.pipeline.get_pipeline_layout_raw(),
+                first_set,
+                descriptor_sets,
+                &[],
+            )
+        };
+        Ok(())
+    }
+
+    /// `data` をプッシュ定数として `offset` バイト目から書き込む
+    ///
+    /// `stages` と範囲がパイプラインのレイアウトで宣言したものに合わなければエラーを返す。
+    /// パディングのバイトを読まないよう、`T` は `Pod` でなければならない
+    pub fn push_constants<T>(
+        &self,
+        stages: ShaderStageFlags,
+        offset: u32,
+        data: &T,
+    ) -> anyhow::Result<()>
+    where
+        T: Pod,
+    {
+        let size = size_of::<T>();
+        validate_push_constants(
+            self.pipeline.get_push_constant_ranges(),
+            stages,
+            offset,
+            size as u32,
+        )?;
+        let bytes = bytemuck::bytes_of(data);
+        unsafe {
+            self.device.cmd_push_constants(
+                self.command_buffer_raw,
+                self.pipeline.get_pipeline_layout_raw(),
+                stages,
+                offset,
+                bytes,
+            )
+        };
         Ok(())
     }
+
+    /// 以降の描画のビューポートを設定する (パイプラインのビューポートが動的な場合のみ)
+    pub fn set_viewport(&self, viewport: Viewport) -> anyhow::Result<()> {
+        self.ensure_dynamic_state(DynamicState::VIEWPORT)?;
+        unsafe {
+            self.device
+                .cmd_set_viewport(self.command_buffer_raw, 0, &[viewport])
+        };
+        Ok(())
+    }
+
+    /// 以降の描画で、`scissor` の外側を切り取る (パイプラインのシザーが動的な場合のみ)
+    pub fn set_scissor(&self, scissor: Rect2D) -> anyhow::Result<()> {
+        self.ensure_dynamic_state(DynamicState::SCISSOR)?;
+        unsafe {
+            self.device
+                .cmd_set_scissor(self.command_buffer_raw, 0, &[scissor])
+        };
+        Ok(())
+    }
+
+    pub fn set_line_width(&self, line_width: f32) -> anyhow::Result<()> {
+        self.ensure_dynamic_state(DynamicState::LINE_WIDTH)?;
+        unsafe {
+            self.device
+                .cmd_set_line_width(self.command_buffer_raw, line_width)
+        };
+        Ok(())
+    }
+
+    pub fn set_blend_constants(&self, blend_constants: [f32; 4]) -> anyhow::Result<()> {
+        self.ensure_dynamic_state(DynamicState::BLEND_CONSTANTS)?;
+        unsafe {
+            self.device
+                .cmd_set_blend_constants(self.command_buffer_raw, &blend_constants)
+        };
+        Ok(())
+    }
+
+    fn ensure_dynamic_state(&self, state: DynamicState) -> anyhow::Result<()> {
+        ensure!(
+            self.pipeline.has_dynamic_state(state),
+            "Pipeline was not created with dynamic state {:?}",
+            state
+        );
+        Ok(())
+    }
+
+    pub fn bind_vertex_buffer(&self, vertex_buffer: &ManagedBuffer) {
+        unsafe {
+            self.device.cmd_bind_vertex_buffers(
+                self.command_buffer_raw,
+                0,
+                &[vertex_buffer.get_buffer_raw()],
+                &[0],
+            )
+        };
+    }
+
+    /// インデックスは `u32` として扱う
+    pub fn bind_index_buffer(&self, index_buffer: &ManagedBuffer) {
+        unsafe {
+            self.device.cmd_bind_index_buffer(
+                self.command_buffer_raw,
+                index_buffer.get_buffer_raw(),
+                0,
+                IndexType::UINT32,
+            )
+        };
+    }
+
+    pub fn draw(&self, vertex_count: u32) {
+        unsafe {
+            self.device
+                .cmd_draw(self.command_buffer_raw, vertex_count, 1, 0, 0)
+        };
+    }
+
+    pub fn draw_indexed(&self, index_count: u32, first_index: u32, vertex_offset: i32) {
+        unsafe {
+            self.device.cmd_draw_indexed(
+                self.command_buffer_raw,
+                index_count,
+                1,
+                first_index,
+                vertex_offset,
+                0,
+            )
+        };
+    }
+
+    /// メッシュの頂点バッファとインデックスバッファをバインドし、すべてのサブメッシュを描画する
+    pub fn draw_mesh(&self, mesh: &Mesh) {
+        self.bind_vertex_buffer(mesh.get_vertex_buffer());
+        self.bind_index_buffer(mesh.get_index_buffer());
+        for submesh in mesh.get_submeshes() {
+            self.draw_indexed(
+                submesh.index_count,
+                submesh.first_index,
+                submesh.vertex_offset,
+            );
+        }
+    }
+}
+
+fn image_layout_barrier(
+    image: Image,
+    old_layout: ImageLayout,
+    new_layout: ImageLayout,
+    src_access_mask: AccessFlags,
+    dst_access_mask: AccessFlags,
+) -> ImageMemoryBarrier {
+    ImageMemoryBarrier::builder()
+        .image(image)
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_access_mask(src_access_mask)
+        .dst_access_mask(dst_access_mask)
+        .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+        .subresource_range(
+            ImageSubresourceRange::builder()
+                .aspect_mask(ImageAspectFlags::COLOR)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(0)
+                .layer_count(1)
+                .build(),
+        )
+        .build()
 }
 
 impl Drop for ManagedCommandBuffer<'_> {
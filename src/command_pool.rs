@@ -1,7 +1,10 @@
 use super::command_buffer::ManagedCommandBuffer;
 use ash::{
     version::DeviceV1_0,
-    vk::{CommandBufferAllocateInfo, CommandBufferLevel, CommandPool, CommandPoolCreateInfo},
+    vk::{
+        CommandBufferAllocateInfo, CommandBufferLevel, CommandPool, CommandPoolCreateFlags,
+        CommandPoolCreateInfo,
+    },
     Device,
 };
 
@@ -15,7 +18,9 @@ impl<'a> ManagedCommandPool<'a> {
         device: &'a Device,
         graphics_queue_family_index: u32,
     ) -> anyhow::Result<ManagedCommandPool<'a>> {
+        // コマンドバッファを記録し直して使い回せるようにする
         let create_info = CommandPoolCreateInfo::builder()
+            .flags(CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
             .queue_family_index(graphics_queue_family_index)
             .build();
         let command_pool_raw = unsafe { device.create_command_pool(&create_info, None) }?;
@@ -0,0 +1,67 @@
+//! 設定ファイル
+//!
+//! 次のような TOML ファイルから読み込む。ファイルが無ければ既定の設定を使う。
+//!
+//! ```toml
+//! # 使う GPU の番号または名前の一部
+//! device = "GeForce"
+//! ```
+
+use crate::physical_device::DeviceSelection;
+use anyhow::Context;
+use serde::Deserialize;
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// 設定ファイルの場所を上書きする環境変数
+pub const CONFIG_ENV_VAR: &str = "GAME_CONFIG";
+
+/// 環境変数か、ユーザの設定ディレクトリから決めた設定ファイルの場所
+pub fn default_path() -> Option<PathBuf> {
+    match std::env::var_os(CONFIG_ENV_VAR) {
+        Some(path) => Some(PathBuf::from(path)),
+        None => dirs::config_dir().map(|dir| dir.join("game").join("config.toml")),
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// 使う GPU (`DeviceSelection::parse` と同じ形式)
+    pub device: Option<String>,
+}
+
+impl Config {
+    pub fn from_toml(source: &str) -> anyhow::Result<Config> {
+        toml::from_str(source).context("Failed to parse config")
+    }
+
+    /// ファイルが存在しない場合は既定の設定を返す
+    pub fn load<P>(path: P) -> anyhow::Result<Config>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                debug!("Config {} does not exist", path.display());
+                return Ok(Config::default());
+            }
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read config from {}", path.display()))
+            }
+        };
+        Config::from_toml(&source).with_context(|| format!("Invalid config in {}", path.display()))
+    }
+
+    /// 設定されていなければ `Auto`
+    pub fn device_selection(&self) -> DeviceSelection {
+        self.device
+            .as_deref()
+            .map_or(DeviceSelection::Auto, DeviceSelection::parse)
+    }
+}
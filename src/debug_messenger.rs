@@ -0,0 +1,132 @@
+//! `VK_EXT_debug_utils` を使って、バリデーションレイヤのメッセージを `log` に流す
+
+use anyhow::Context;
+use ash::{
+    extensions::ext::DebugUtils,
+    vk::{
+        Bool32, DebugUtilsMessageSeverityFlagsEXT, DebugUtilsMessageTypeFlagsEXT,
+        DebugUtilsMessengerCallbackDataEXT, DebugUtilsMessengerCreateInfoEXT,
+        DebugUtilsMessengerEXT, FALSE,
+    },
+    Entry, Instance,
+};
+use std::{
+    ffi::CStr,
+    os::raw::c_void,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// コールバックとやり取りする状態
+///
+/// コールバックの中でパニックするとプロセスごと落ちるので、エラーを溜めておいて後でパニックする
+#[derive(Default)]
+pub struct DebugState {
+    panic_on_error: AtomicBool,
+    errors: Mutex<Vec<String>>,
+}
+
+impl DebugState {
+    pub fn set_panic_on_error(&self, enabled: bool) {
+        self.panic_on_error.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn panics_on_error(&self) -> bool {
+        self.panic_on_error.load(Ordering::SeqCst)
+    }
+
+    /// 溜まっているエラーメッセージを取り出す
+    pub fn take_errors(&self) -> Vec<String> {
+        std::mem::take(&mut *self.errors.lock().unwrap())
+    }
+}
+
+/// インスタンスより先に破棄しなければならないので、`ManagedInstance` が明示的に破棄する
+///
+/// コールバックに渡す `DebugState` は、インスタンスの破棄時のメッセージも受け取れるように
+/// `ManagedInstance` がインスタンスより長く持っておく
+pub struct DebugMessenger {
+    debug_utils_loader: DebugUtils,
+    messenger_raw: DebugUtilsMessengerEXT,
+}
+
+impl DebugMessenger {
+    /// インスタンスの作成時と破棄時のメッセージも受け取れるように、`InstanceCreateInfo` に繋ぐための情報を作る
+    pub fn create_info(state: &DebugState) -> DebugUtilsMessengerCreateInfoEXT {
+        DebugUtilsMessengerCreateInfoEXT::builder()
+            .message_severity(
+                DebugUtilsMessageSeverityFlagsEXT::ERROR
+                    | DebugUtilsMessageSeverityFlagsEXT::WARNING
+                    | DebugUtilsMessageSeverityFlagsEXT::INFO
+                    | DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
+            )
+            .message_type(
+                DebugUtilsMessageTypeFlagsEXT::GENERAL
+                    | DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                    | DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            )
+            .pfn_user_callback(Some(debug_callback))
+            .user_data(state as *const DebugState as *mut c_void)
+            .build()
+    }
+
+    /// `state` はメッセンジャーとインスタンスの両方より長生きしなければならない
+    pub fn new(
+        entry: &Entry,
+        instance: &Instance,
+        state: &DebugState,
+    ) -> anyhow::Result<DebugMessenger> {
+        let debug_utils_loader = DebugUtils::new(entry, instance);
+        let create_info = DebugMessenger::create_info(state);
+        let messenger_raw =
+            unsafe { debug_utils_loader.create_debug_utils_messenger(&create_info, None) }
+                .context("Failed to create debug messenger")?;
+        Ok(DebugMessenger {
+            debug_utils_loader,
+            messenger_raw,
+        })
+    }
+}
+
+impl Drop for DebugMessenger {
+    fn drop(&mut self) {
+        unsafe {
+            self.debug_utils_loader
+                .destroy_debug_utils_messenger(self.messenger_raw, None)
+        };
+        trace!("Debug messenger was destroyed");
+    }
+}
+
+unsafe extern "system" fn debug_callback(
+    message_severity: DebugUtilsMessageSeverityFlagsEXT,
+    message_type: DebugUtilsMessageTypeFlagsEXT,
+    callback_data: *const DebugUtilsMessengerCallbackDataEXT,
+    user_data: *mut c_void,
+) -> Bool32 {
+    let message = if callback_data.is_null() || (*callback_data).p_message.is_null() {
+        "(no message)".into()
+    } else {
+        CStr::from_ptr((*callback_data).p_message).to_string_lossy()
+    };
+    if message_severity.contains(DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        error!("[Vulkan {:?}] {}", message_type, message);
+        if let Some(state) = (user_data as *const DebugState).as_ref() {
+            if state.panics_on_error() {
+                if let Ok(mut errors) = state.errors.lock() {
+                    errors.push(message.into_owned());
+                }
+            }
+        }
+    } else if message_severity.contains(DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        warn!("[Vulkan {:?}] {}", message_type, message);
+    } else if message_severity.contains(DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        info!("[Vulkan {:?}] {}", message_type, message);
+    } else {
+        trace!("[Vulkan {:?}] {}", message_type, message);
+    }
+    // 呼び出し元の Vulkan の関数は中断しない
+    FALSE
+}
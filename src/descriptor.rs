@@ -0,0 +1,453 @@
+//! ディスクリプタセットのレイアウト・プール・書き込み
+
+use crate::{buffer::ManagedBuffer, texture::ManagedTexture};
+use anyhow::Context;
+use ash::{
+    version::DeviceV1_0,
+    vk::{
+        DescriptorBufferInfo, DescriptorImageInfo, DescriptorPool, DescriptorPoolCreateInfo,
+        DescriptorPoolResetFlags, DescriptorPoolSize, DescriptorSet, DescriptorSetAllocateInfo,
+        DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo,
+        DescriptorType, ImageLayout, ShaderStageFlags, WriteDescriptorSet, WHOLE_SIZE,
+    },
+    Device,
+};
+
+/// レイアウトの中の 1 つのバインディング
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub binding: u32,
+    pub descriptor_type: DescriptorType,
+    /// 配列でなければ 1
+    pub count: u32,
+    pub stages: ShaderStageFlags,
+}
+
+impl DescriptorBinding {
+    pub fn uniform_buffer(binding: u32, stages: ShaderStageFlags) -> DescriptorBinding {
+        DescriptorBinding {
+            binding,
+            descriptor_type: DescriptorType::UNIFORM_BUFFER,
+            count: 1,
+            stages,
+        }
+    }
+
+    pub fn storage_buffer(binding: u32, stages: ShaderStageFlags) -> DescriptorBinding {
+        DescriptorBinding {
+            binding,
+            descriptor_type: DescriptorType::STORAGE_BUFFER,
+            count: 1,
+            stages,
+        }
+    }
+
+    /// テクスチャとサンプラの組
+    pub fn combined_image_sampler(binding: u32, stages: ShaderStageFlags) -> DescriptorBinding {
+        DescriptorBinding {
+            binding,
+            descriptor_type: DescriptorType::COMBINED_IMAGE_SAMPLER,
+            count: 1,
+            stages,
+        }
+    }
+}
+
+/// 自動で解放される、ディスクリプタセットレイアウトのラッパー
+pub struct ManagedDescriptorSetLayout<'a> {
+    device: &'a Device,
+    layout_raw: DescriptorSetLayout,
+    bindings: Vec<DescriptorBinding>,
+}
+
+impl<'a> ManagedDescriptorSetLayout<'a> {
+    pub fn new(
+        device: &'a Device,
+        bindings: &[DescriptorBinding],
+    ) -> anyhow::Result<ManagedDescriptorSetLayout<'a>> {
+        for (i, binding) in bindings.iter().enumerate() {
+            ensure!(
+                bindings[..i]
+                    .iter()
+                    .all(|other| other.binding != binding.binding),
+                "Binding {} is declared more than once",
+                binding.binding
+            );
+        }
+        let layout_bindings: Vec<DescriptorSetLayoutBinding> = bindings
+            .iter()
+            .map(|binding| {
+                DescriptorSetLayoutBinding::builder()
+                    .binding(binding.binding)
+                    .descriptor_type(binding.descriptor_type)
+                    .descriptor_count(binding.count)
+                    .stage_flags(binding.stages)
+                    .build()
+            })
+            .collect();
+        let create_info = DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&layout_bindings)
+            .build();
+        let layout_raw = unsafe { device.create_descriptor_set_layout(&create_info, None) }
+            .context("Failed to create DescriptorSetLayout")?;
+        Ok(ManagedDescriptorSetLayout {
+            device,
+            layout_raw,
+            bindings: bindings.to_vec(),
+        })
+    }
+
+    pub fn get_descriptor_set_layout_raw(&self) -> DescriptorSetLayout {
+        self.layout_raw
+    }
+
+    pub fn get_bindings(&self) -> &[DescriptorBinding] {
+        &self.bindings
+    }
+
+    /// このレイアウトのセットを 1 つ確保するのに必要な、種類ごとのディスクリプタの数
+    fn descriptor_counts(&self) -> Vec<(DescriptorType, u32)> {
+        let mut counts: Vec<(DescriptorType, u32)> = Vec::new();
+        for binding in self.bindings.iter() {
+            match counts
+                .iter_mut()
+                .find(|(descriptor_type, _)| *descriptor_type == binding.descriptor_type)
+            {
+                Some((_, count)) => *count += binding.count,
+                None => counts.push((binding.descriptor_type, binding.count)),
+            }
+        }
+        counts
+    }
+}
+
+impl Drop for ManagedDescriptorSetLayout<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .destroy_descriptor_set_layout(self.layout_raw, None)
+        };
+        trace!("DescriptorSetLayout was destroyed");
+    }
+}
+
+/// 最初のプールで確保できるセットの数 (足りなくなるたびに倍にしていく)
+const INITIAL_SETS_PER_POOL: u32 = 16;
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+/// セット 1 つあたりに用意しておく、種類ごとのディスクリプタの数
+const DESCRIPTORS_PER_SET: [(DescriptorType, u32); 3] = [
+    (DescriptorType::UNIFORM_BUFFER, 2),
+    (DescriptorType::STORAGE_BUFFER, 1),
+    (DescriptorType::COMBINED_IMAGE_SAMPLER, 2),
+];
+
+/// 1 つのプールから確保できるセットとディスクリプタの数
+#[derive(Clone, Debug)]
+struct PoolCapacity {
+    sets: u32,
+    descriptors: Vec<DescriptorPoolSize>,
+}
+
+impl PoolCapacity {
+    /// `counts` のセットを `set_count` 個確保できるなら、その分を差し引く
+    fn take(&mut self, counts: &[(DescriptorType, u32)], set_count: u32) -> bool {
+        let fits = set_count <= self.sets
+            && counts.iter().all(|&(ty, per_set)| {
+                self.descriptors
+                    .iter()
+                    .any(|size| size.ty == ty && per_set * set_count <= size.descriptor_count)
+                    || per_set == 0
+            });
+        if fits {
+            self.sets -= set_count;
+            for &(ty, per_set) in counts {
+                if let Some(size) = self.descriptors.iter_mut().find(|size| size.ty == ty) {
+                    size.descriptor_count -= per_set * set_count;
+                }
+            }
+        }
+        fits
+    }
+}
+
+/// 足りなくなると新しいプールを作って広がる、ディスクリプタプールのラッパー
+///
+/// 確保したセットは個別には解放せず、`reset` かプールの破棄でまとめて解放する
+pub struct ManagedDescriptorPool<'a> {
+    device: &'a Device,
+    /// 最後の要素に空きがある可能性がある
+    pools: Vec<DescriptorPool>,
+    /// 最後のプールの容量と、その残り
+    ///
+    /// 空きのないプールからの確保はエラーを返さない実装もあるので、自分で数えておく
+    last_capacity: Option<(PoolCapacity, PoolCapacity)>,
+    next_sets_per_pool: u32,
+}
+
+impl<'a> ManagedDescriptorPool<'a> {
+    pub fn new(device: &'a Device) -> ManagedDescriptorPool<'a> {
+        ManagedDescriptorPool {
+            device,
+            pools: Vec::new(),
+            last_capacity: None,
+            next_sets_per_pool: INITIAL_SETS_PER_POOL,
+        }
+    }
+
+    /// `layout` のセットを 1 つ確保する
+    pub fn allocate(
+        &mut self,
+        layout: &ManagedDescriptorSetLayout,
+    ) -> anyhow::Result<DescriptorSet> {
+        Ok(self.allocate_many(layout, 1)?[0])
+    }
+
+    /// フレームごとに使い分けるための、`layout` のセットを `frame_count` 個確保する
+    ///
+    /// 返す配列の添字は `FramesInFlight::get_current_frame` に対応する
+    pub fn allocate_per_frame(
+        &mut self,
+        layout: &ManagedDescriptorSetLayout,
+        frame_count: usize,
+    ) -> anyhow::Result<Vec<DescriptorSet>> {
+        self.allocate_many(layout, frame_count)
+    }
+
+    fn allocate_many(
+        &mut self,
+        layout: &ManagedDescriptorSetLayout,
+        count: usize,
+    ) -> anyhow::Result<Vec<DescriptorSet>> {
+        ensure!(count > 0, "At least one descriptor set must be allocated");
+        let set_count = count as u32;
+        let counts = layout.descriptor_counts();
+        let fits = match self.last_capacity.as_mut() {
+            Some((_, remaining)) => remaining.take(&counts, set_count),
+            None => false,
+        };
+        let pool = if fits {
+            *self.pools.last().unwrap()
+        } else {
+            trace!("DescriptorPool is full, creating a new one");
+            let pool = self.create_pool(&counts, set_count)?;
+            let (_, remaining) = self.last_capacity.as_mut().unwrap();
+            ensure!(
+                remaining.take(&counts, set_count),
+                "New DescriptorPool is too small"
+            );
+            pool
+        };
+        let set_layouts = vec![layout.get_descriptor_set_layout_raw(); count];
+        let allocate_info = DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts)
+            .build();
+        unsafe { self.device.allocate_descriptor_sets(&allocate_info) }
+            .context("Failed to allocate descriptor sets")
+    }
+
+    /// 少なくとも `counts` のセットを `count` 個確保できるプールを作る
+    fn create_pool(
+        &mut self,
+        counts: &[(DescriptorType, u32)],
+        count: u32,
+    ) -> anyhow::Result<DescriptorPool> {
+        let max_sets = self.next_sets_per_pool.max(count);
+        let mut pool_sizes: Vec<DescriptorPoolSize> = DESCRIPTORS_PER_SET
+            .iter()
+            .map(|&(ty, per_set)| DescriptorPoolSize {
+                ty,
+                descriptor_count: per_set * max_sets,
+            })
+            .collect();
+        for &(ty, per_set) in counts {
+            let required = per_set * count;
+            match pool_sizes.iter_mut().find(|size| size.ty == ty) {
+                Some(size) => size.descriptor_count = size.descriptor_count.max(required),
+                None => pool_sizes.push(DescriptorPoolSize {
+                    ty,
+                    descriptor_count: required,
+                }),
+            }
+        }
+        let create_info = DescriptorPoolCreateInfo::builder()
+            .max_sets(max_sets)
+            .pool_sizes(&pool_sizes)
+            .build();
+        let pool = unsafe { self.device.create_descriptor_pool(&create_info, None) }
+            .context("Failed to create DescriptorPool")?;
+        debug!("Created DescriptorPool for {} sets", max_sets);
+        self.pools.push(pool);
+        let capacity = PoolCapacity {
+            sets: max_sets,
+            descriptors: pool_sizes,
+        };
+        self.last_capacity = Some((capacity.clone(), capacity));
+        self.next_sets_per_pool = (max_sets * 2).min(MAX_SETS_PER_POOL);
+        Ok(pool)
+    }
+
+    /// 確保したすべてのセットを解放する (プールは次の確保で使い回す)
+    ///
+    /// GPU が使用中のセットがないことを確認してから呼ぶこと
+    pub fn reset(&mut self) -> anyhow::Result<()> {
+        // 一番大きい最後のプールだけを残す
+        if let Some(last) = self.pools.pop() {
+            for pool in self.pools.drain(..) {
+                unsafe { self.device.destroy_descriptor_pool(pool, None) };
+            }
+            unsafe {
+                self.device
+                    .reset_descriptor_pool(last, DescriptorPoolResetFlags::empty())
+            }
+            .context("Failed to reset DescriptorPool")?;
+            self.pools.push(last);
+            if let Some((capacity, remaining)) = self.last_capacity.as_mut() {
+                *remaining = capacity.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// 作成済みのプールの数
+    pub fn get_pool_count(&self) -> usize {
+        self.pools.len()
+    }
+}
+
+impl Drop for ManagedDescriptorPool<'_> {
+    fn drop(&mut self) {
+        for pool in self.pools.drain(..) {
+            unsafe { self.device.destroy_descriptor_pool(pool, None) };
+        }
+        trace!("DescriptorPool was destroyed");
+    }
+}
+
+enum DescriptorInfo {
+    Buffer(usize),
+    Image(usize),
+}
+
+struct PendingWrite {
+    set: DescriptorSet,
+    binding: u32,
+    descriptor_type: DescriptorType,
+    info: DescriptorInfo,
+}
+
+/// ディスクリプタセットへの書き込みをまとめておき、`ManagedLogicalDevice::update_descriptor_sets` で一度に反映する
+#[derive(Default)]
+pub struct DescriptorWriter {
+    buffer_infos: Vec<DescriptorBufferInfo>,
+    image_infos: Vec<DescriptorImageInfo>,
+    writes: Vec<PendingWrite>,
+}
+
+impl DescriptorWriter {
+    pub fn new() -> DescriptorWriter {
+        DescriptorWriter::default()
+    }
+
+    /// バッファの `offset` から `range` バイトを書き込む
+    pub fn write_buffer(
+        &mut self,
+        set: DescriptorSet,
+        binding: u32,
+        descriptor_type: DescriptorType,
+        buffer: &ManagedBuffer,
+        offset: u64,
+        range: u64,
+    ) -> &mut DescriptorWriter {
+        self.buffer_infos.push(
+            DescriptorBufferInfo::builder()
+                .buffer(buffer.get_buffer_raw())
+                .offset(offset)
+                .range(range)
+                .build(),
+        );
+        self.writes.push(PendingWrite {
+            set,
+            binding,
+            descriptor_type,
+            info: DescriptorInfo::Buffer(self.buffer_infos.len() - 1),
+        });
+        self
+    }
+
+    /// バッファ全体をユニフォームバッファとして書き込む
+    pub fn write_uniform_buffer(
+        &mut self,
+        set: DescriptorSet,
+        binding: u32,
+        buffer: &ManagedBuffer,
+    ) -> &mut DescriptorWriter {
+        self.write_buffer(
+            set,
+            binding,
+            DescriptorType::UNIFORM_BUFFER,
+            buffer,
+            0,
+            WHOLE_SIZE,
+        )
+    }
+
+    /// テクスチャとそのサンプラを書き込む
+    pub fn write_texture(
+        &mut self,
+        set: DescriptorSet,
+        binding: u32,
+        texture: &ManagedTexture,
+    ) -> &mut DescriptorWriter {
+        self.image_infos.push(
+            DescriptorImageInfo::builder()
+                .image_view(texture.get_image_view_raw())
+                .sampler(texture.get_sampler().get_sampler_raw())
+                .image_layout(ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .build(),
+        );
+        self.writes.push(PendingWrite {
+            set,
+            binding,
+            descriptor_type: DescriptorType::COMBINED_IMAGE_SAMPLER,
+            info: DescriptorInfo::Image(self.image_infos.len() - 1),
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub(crate) fn update(&self, device: &Device) {
+        if self.writes.is_empty() {
+            return;
+        }
+        let writes: Vec<WriteDescriptorSet> = self
+            .writes
+            .iter()
+            .map(|write| {
+                let builder = WriteDescriptorSet::builder()
+                    .dst_set(write.set)
+                    .dst_binding(write.binding)
+                    .dst_array_element(0)
+                    .descriptor_type(write.descriptor_type);
+                match write.info {
+                    DescriptorInfo::Buffer(index) => builder
+                        .buffer_info(&self.buffer_infos[index..=index])
+                        .build(),
+                    DescriptorInfo::Image(index) => {
+                        builder.image_info(&self.image_infos[index..=index]).build()
+                    }
+                }
+            })
+            .collect();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+        trace!("Updated {} descriptors", writes.len());
+    }
+}
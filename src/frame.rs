@@ -0,0 +1,155 @@
+use crate::{
+    command_buffer::ManagedCommandBuffer,
+    command_pool::ManagedCommandPool,
+    swapchain::ManagedSwapchain,
+    sync::{ManagedFence, ManagedSemaphore},
+};
+use anyhow::Context;
+use ash::{
+    version::DeviceV1_0,
+    vk::{Fence, PipelineStageFlags, Queue, Result as VkError},
+    Device,
+};
+
+/// 1 フレーム分の描画に使うコマンドバッファと同期オブジェクト
+struct Frame<'a> {
+    command_buffer: ManagedCommandBuffer<'a>,
+    /// スワップチェーンのイメージが取得できたらシグナルされる
+    image_available: ManagedSemaphore<'a>,
+    /// 描画が終わったらシグナルされ、表示の開始を待たせる
+    render_finished: ManagedSemaphore<'a>,
+    /// 描画が終わったらシグナルされ、このフレームのリソースを使い回してよいことを CPU に伝える
+    in_flight: ManagedFence<'a>,
+}
+
+/// 複数のフレームを並行して処理するための描画ループ
+///
+/// GPU がフレームを描画している間に、CPU は次のフレームのコマンドを記録できる
+pub struct FramesInFlight<'a> {
+    device: &'a Device,
+    graphics_queue: Queue,
+    frames: Vec<Frame<'a>>,
+    current_frame: usize,
+    /// スワップチェーンのイメージごとに、そのイメージを使っているフレームのフェンス
+    images_in_flight: Vec<Fence>,
+}
+
+impl<'a> FramesInFlight<'a> {
+    pub fn new(
+        device: &'a Device,
+        command_pool: &'a ManagedCommandPool,
+        graphics_queue: Queue,
+        frame_count: usize,
+    ) -> anyhow::Result<FramesInFlight<'a>> {
+        ensure!(frame_count > 0, "At least one frame must be in flight");
+        let frames = (0..frame_count)
+            .map(|_| {
+                Ok(Frame {
+                    command_buffer: command_pool.allocate_command_buffer()?,
+                    image_available: ManagedSemaphore::new(device)?,
+                    render_finished: ManagedSemaphore::new(device)?,
+                    // 最初のフレームで待ち続けないように、シグナル済みで作る
+                    in_flight: ManagedFence::new(device, true)?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(FramesInFlight {
+            device,
+            graphics_queue,
+            frames,
+            current_frame: 0,
+            images_in_flight: Vec::new(),
+        })
+    }
+
+    /// 1 フレームを描画して表示する
+    ///
+    /// `record` には、このフレームのコマンドバッファと描画先のスワップチェーンのイメージのインデックスが渡される。
+    /// スワップチェーンがサーフェスに合わなくなっていて、作り直す必要がある場合は `true` を返す
+    pub fn draw_frame<F>(&mut self, swapchain: &ManagedSwapchain, record: F) -> anyhow::Result<bool>
+    where
+        F: FnOnce(&ManagedCommandBuffer, usize) -> anyhow::Result<()>,
+    {
+        let frame = &self.frames[self.current_frame];
+        frame.in_flight.wait()?;
+
+        let (image_index, suboptimal) = match swapchain
+            .acquire_next_image(frame.image_available.get_semaphore_raw(), Fence::null())
+        {
+            Ok(result) => result,
+            // イメージを取得できていないので、何も送らずに作り直してもらう
+            Err(VkError::ERROR_OUT_OF_DATE_KHR) => return Ok(true),
+            Err(err) => return Err(err).context("Failed to acquire next swapchain image"),
+        };
+        let image_index = image_index as usize;
+
+        // 前のフレームがまだこのイメージに描画しているなら、それが終わるのを待つ
+        if self.images_in_flight.len() < swapchain.get_image_count() {
+            self.images_in_flight
+                .resize(swapchain.get_image_count(), Fence::null());
+        }
+        let image_in_flight = self.images_in_flight[image_index];
+        if image_in_flight != Fence::null() {
+            unsafe {
+                self.device
+                    .wait_for_fences(&[image_in_flight], true, u64::MAX)
+            }
+            .context("Failed to wait for swapchain image")?;
+        }
+        self.images_in_flight[image_index] = frame.in_flight.get_fence_raw();
+
+        record(&frame.command_buffer, image_index)?;
+
+        frame.in_flight.reset()?;
+        frame.command_buffer.submit(
+            &self.graphics_queue,
+            &[frame.image_available.get_semaphore_raw()],
+            &[PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
+            &[frame.render_finished.get_semaphore_raw()],
+            frame.in_flight.get_fence_raw(),
+        )?;
+        let present_result = swapchain.present(
+            image_index as u32,
+            &[frame.render_finished.get_semaphore_raw()],
+        );
+        self.current_frame = (self.current_frame + 1) % self.frames.len();
+        match present_result {
+            Ok(present_suboptimal) => Ok(suboptimal || present_suboptimal),
+            Err(VkError::ERROR_OUT_OF_DATE_KHR) => Ok(true),
+            Err(err) => Err(err).context("Failed to present swapchain image"),
+        }
+    }
+
+    /// 次の `draw_frame` で使うフレームの番号 (`0..get_frame_count()`)
+    ///
+    /// フレームごとに用意したディスクリプタセットやユニフォームバッファを選ぶのに使う
+    pub fn get_current_frame(&self) -> usize {
+        self.current_frame
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// スワップチェーンを作り直した後に、古いイメージとフレームの対応を忘れる
+    pub fn reset_images_in_flight(&mut self) {
+        self.images_in_flight.clear();
+    }
+
+    /// すべてのフレームの描画が終わるまで待つ
+    pub fn wait_all(&self) -> anyhow::Result<()> {
+        for frame in self.frames.iter() {
+            frame.in_flight.wait()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FramesInFlight<'_> {
+    fn drop(&mut self) {
+        // 使用中のセマフォやコマンドバッファを破棄しないように待つ
+        if let Err(err) = self.wait_all() {
+            error!("{:?}", err);
+        }
+    }
+}
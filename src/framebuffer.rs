@@ -1,14 +1,34 @@
-use crate::{optimized_image::ManagedAndOptimizedImage, render_pass::ManagedRenderPass};
+use crate::render_pass::ManagedRenderPass;
 use ash::{
     version::DeviceV1_0,
-    vk::{Framebuffer, FramebufferCreateInfo},
+    vk::{Framebuffer, FramebufferCreateInfo, ImageView},
     Device,
 };
+use std::marker::PhantomData;
+
+/// フレームバッファに繋ぐイメージのビュー
+///
+/// ビューを持っているイメージを借用しているので、フレームバッファより先にイメージを破棄できない
+#[derive(Clone, Copy)]
+pub struct AttachmentView<'a> {
+    image_view: ImageView,
+    _owner: PhantomData<&'a ()>,
+}
+
+impl<'a> AttachmentView<'a> {
+    /// `image_view` は `owner` が破棄するまで有効なものでなければならない
+    pub(crate) fn new<T>(_owner: &'a T, image_view: ImageView) -> AttachmentView<'a> {
+        AttachmentView {
+            image_view,
+            _owner: PhantomData,
+        }
+    }
+}
 
 pub struct ManagedFramebuffer<'a> {
     device: &'a Device,
     _render_pass: &'a ManagedRenderPass<'a>,
-    _connectable_image: &'a ManagedAndOptimizedImage<'a>,
+    _attachment: AttachmentView<'a>,
     framebuffer_raw: Framebuffer,
 }
 
@@ -16,7 +36,7 @@ impl<'a> ManagedFramebuffer<'a> {
     pub fn new(
         device: &'a Device,
         render_pass: &'a ManagedRenderPass<'a>,
-        connectable_image: &'a ManagedAndOptimizedImage<'a>,
+        attachment: AttachmentView<'a>,
         width: u32,
         height: u32,
     ) -> anyhow::Result<ManagedFramebuffer<'a>> {
@@ -25,13 +45,13 @@ impl<'a> ManagedFramebuffer<'a> {
             .height(height)
             .layers(1)
             .render_pass(render_pass.get_render_pass_raw())
-            .attachments(&[connectable_image.get_image_view_raw()])
+            .attachments(&[attachment.image_view])
             .build();
         let framebuffer_raw = unsafe { device.create_framebuffer(&create_info, None) }?;
         Ok(ManagedFramebuffer {
             device,
             _render_pass: render_pass,
-            _connectable_image: connectable_image,
+            _attachment: attachment,
             framebuffer_raw,
         })
     }
@@ -1,7 +1,9 @@
 //! GLFW 関連
 
+use crate::input::gamepad::Gamepads;
 use anyhow::Context;
-use glfw::{ClientApiHint, Glfw, Window, WindowHint, WindowMode};
+use glfw::{ClientApiHint, Glfw, Window, WindowEvent, WindowHint, WindowMode};
+use std::{path::Path, sync::mpsc::Receiver};
 
 pub struct GlfwWrapper {
     glfw_raw: Glfw,
@@ -20,15 +22,46 @@ impl GlfwWrapper {
         width: u32,
         height: u32,
         title: Title,
-    ) -> anyhow::Result<Window>
+    ) -> anyhow::Result<(Window, Receiver<(f64, WindowEvent)>)>
     where
         Title: ToString,
     {
-        let (window, _event_receiver) = self
-            .glfw_raw
+        self.glfw_raw
             .create_window(width, height, &title.to_string(), WindowMode::Windowed)
-            .context("Failed to create window")?;
-        Ok(window)
+            .context("Failed to create window")
+    }
+
+    /// 溜まっているウィンドウイベントを処理する
+    pub fn poll_events(&self) {
+        // `Glfw` は初期化済みであることを表すトークンに過ぎないので、複製して可変参照を得てもよい
+        self.glfw_raw.clone().poll_events();
+    }
+
+    /// ウィンドウイベントが届くまで待ってから処理する
+    pub fn wait_events(&self) {
+        self.glfw_raw.clone().wait_events();
+    }
+
+    /// 接続されているゲームパッドの状態を読み取るためのオブジェクトを作成する
+    pub fn create_gamepads(&self) -> Gamepads {
+        Gamepads::new(self.glfw_raw.clone())
+    }
+
+    /// SDL_GameControllerDB の形式 (`gamecontrollerdb.txt`) のマッピングを読み込む
+    pub fn load_gamepad_mappings<P>(&self, path: P) -> anyhow::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mappings = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read gamepad mappings from {}", path.display()))?;
+        ensure!(
+            self.glfw_raw.update_gamepad_mappings(&mappings),
+            "Failed to parse gamepad mappings in {}",
+            path.display()
+        );
+        debug!("Gamepad mappings were loaded from {}", path.display());
+        Ok(())
     }
 
     pub fn get_required_instance_extensions(&self) -> anyhow::Result<Vec<String>> {
@@ -0,0 +1,57 @@
+//! ウィンドウを使わないオフスクリーン描画
+
+use crate::{
+    instance::ManagedInstance,
+    shader::{self, ShaderLibrary},
+    vertex::{ColorVertex2D, TRIANGLE_VERTICES},
+};
+use ash::vk::BufferUsageFlags;
+use image::RgbaImage;
+
+/// 三角形をオフスクリーンで描画し、その結果を CPU 側の画像として返す
+pub fn render_triangle(
+    instance: &ManagedInstance,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<RgbaImage> {
+    ensure!(
+        width > 0 && height > 0,
+        "Image size must not be zero ({}x{})",
+        width,
+        height
+    );
+    let logical_device = instance.create_logical_device(None)?;
+    let command_pool = logical_device.create_command_pool()?;
+    let graphics_queue = logical_device.get_graphics_queue();
+    let command_buffer = command_pool.allocate_command_buffer()?;
+    let optimized_image = logical_device.create_optimized_image(width, height)?;
+    let linear_image = logical_device.create_linear_image(width, height)?;
+    let render_pass = logical_device.create_render_pass()?;
+    let library = ShaderLibrary::new(shader::default_dir());
+    let pipeline = render_pass.create_graphics_pipeline::<ColorVertex2D>(&library, &[], &[])?;
+    let vertex_buffer = logical_device.create_device_local_buffer(
+        &command_pool,
+        BufferUsageFlags::VERTEX_BUFFER,
+        &TRIANGLE_VERTICES,
+    )?;
+    let framebuffer =
+        logical_device.create_framebuffer(&render_pass, &optimized_image, width, height)?;
+    command_buffer.draw(
+        &graphics_queue,
+        &render_pass,
+        &framebuffer,
+        &pipeline,
+        &vertex_buffer,
+        TRIANGLE_VERTICES.len() as u32,
+        width,
+        height,
+    )?;
+    command_buffer.copy_to_linear_image(
+        &graphics_queue,
+        &optimized_image,
+        &linear_image,
+        width,
+        height,
+    )?;
+    linear_image.read_pixels(width, height)
+}
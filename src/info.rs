@@ -0,0 +1,301 @@
+//! 不具合報告に添付してもらうための、Vulkan 実装の情報
+
+use crate::instance::ManagedInstance;
+use anyhow::Context;
+use ash::{
+    version::{EntryV1_0, InstanceV1_0},
+    vk::{
+        version_major, version_minor, version_patch, ExtensionProperties, Format, PhysicalDevice,
+        PhysicalDeviceLimits, PhysicalDeviceMemoryProperties,
+    },
+    Instance,
+};
+use serde::Serialize;
+use std::{ffi::CStr, fmt::Debug, os::raw::c_char};
+
+/// 対応状況を報告するフォーマット (このゲームで使っているもの)
+const REPORTED_FORMATS: &[Format] = &[
+    Format::R8G8B8A8_UNORM,
+    Format::R8G8B8A8_SRGB,
+    Format::B8G8R8A8_UNORM,
+    Format::B8G8R8A8_SRGB,
+];
+
+#[derive(Debug, Serialize)]
+pub struct SystemInfo {
+    /// ローダが対応している Vulkan のバージョン
+    pub instance_version: String,
+    pub instance_extensions: Vec<ExtensionInfo>,
+    pub instance_layers: Vec<LayerInfo>,
+    pub devices: Vec<DeviceInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExtensionInfo {
+    pub name: String,
+    pub spec_version: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LayerInfo {
+    pub name: String,
+    pub description: String,
+    pub spec_version: String,
+    pub implementation_version: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceInfo {
+    pub index: usize,
+    pub name: String,
+    pub device_type: String,
+    pub api_version: String,
+    pub driver_version: u32,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    /// このゲームで使えない場合は、その理由 (ウィンドウへの表示に対応しているかは調べない)
+    pub rejection: Option<String>,
+    pub extensions: Vec<ExtensionInfo>,
+    pub queue_families: Vec<QueueFamilyInfo>,
+    pub memory_heaps: Vec<MemoryHeapInfo>,
+    pub memory_types: Vec<MemoryTypeInfo>,
+    pub limits: LimitsInfo,
+    pub formats: Vec<FormatInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueFamilyInfo {
+    pub index: u32,
+    pub queue_count: u32,
+    pub flags: Vec<String>,
+    pub timestamp_valid_bits: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemoryHeapInfo {
+    pub index: u32,
+    pub size: u64,
+    pub flags: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemoryTypeInfo {
+    pub index: u32,
+    pub heap_index: u32,
+    pub flags: Vec<String>,
+}
+
+/// 制限のうち、このゲームに関係しそうなもの
+#[derive(Debug, Serialize)]
+pub struct LimitsInfo {
+    pub max_image_dimension_2d: u32,
+    pub max_framebuffer_width: u32,
+    pub max_framebuffer_height: u32,
+    pub max_viewports: u32,
+    pub max_push_constants_size: u32,
+    pub max_memory_allocation_count: u32,
+    pub max_sampler_allocation_count: u32,
+    pub max_bound_descriptor_sets: u32,
+    pub max_per_stage_descriptor_samplers: u32,
+    pub max_per_stage_descriptor_uniform_buffers: u32,
+    pub max_uniform_buffer_range: u32,
+    pub max_vertex_input_attributes: u32,
+    pub max_vertex_input_bindings: u32,
+    pub max_sampler_anisotropy: f32,
+    pub min_uniform_buffer_offset_alignment: u64,
+    pub optimal_buffer_copy_row_pitch_alignment: u64,
+    pub non_coherent_atom_size: u64,
+    pub buffer_image_granularity: u64,
+    pub timestamp_period: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FormatInfo {
+    pub format: String,
+    pub linear_tiling_features: Vec<String>,
+    pub optimal_tiling_features: Vec<String>,
+    pub buffer_features: Vec<String>,
+}
+
+/// インスタンスから見えるものをすべて集める
+pub fn collect(instance: &ManagedInstance) -> anyhow::Result<SystemInfo> {
+    let entry = instance.get_entry();
+    let instance_raw = instance.get_instance_raw();
+    let instance_version = entry
+        .try_enumerate_instance_version()
+        .context("Failed to enumerate instance version")?
+        // vkEnumerateInstanceVersion が無いのは 1.0 のローダだけ
+        .map_or_else(|| "1.0.0".to_owned(), format_version);
+    let instance_extensions = entry
+        .enumerate_instance_extension_properties()
+        .context("Failed to enumerate instance extension properties")?
+        .iter()
+        .map(extension_info)
+        .collect();
+    let instance_layers = entry
+        .enumerate_instance_layer_properties()
+        .context("Failed to enumerate instance layer properties")?
+        .iter()
+        .map(|layer| LayerInfo {
+            name: c_chars_to_string(&layer.layer_name),
+            description: c_chars_to_string(&layer.description),
+            spec_version: format_version(layer.spec_version),
+            implementation_version: layer.implementation_version,
+        })
+        .collect();
+    let devices = instance
+        .list_physical_devices(None)?
+        .into_iter()
+        .map(|candidate| {
+            collect_device(
+                instance_raw,
+                candidate.physical_device,
+                candidate.index,
+                candidate.rejection,
+            )
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok(SystemInfo {
+        instance_version,
+        instance_extensions,
+        instance_layers,
+        devices,
+    })
+}
+
+fn collect_device(
+    instance_raw: &Instance,
+    physical_device: PhysicalDevice,
+    index: usize,
+    rejection: Option<String>,
+) -> anyhow::Result<DeviceInfo> {
+    let properties = unsafe { instance_raw.get_physical_device_properties(physical_device) };
+    let extensions = unsafe { instance_raw.enumerate_device_extension_properties(physical_device) }
+        .context("Failed to enumerate device extension properties")?
+        .iter()
+        .map(extension_info)
+        .collect();
+    let queue_families =
+        unsafe { instance_raw.get_physical_device_queue_family_properties(physical_device) }
+            .iter()
+            .enumerate()
+            .map(|(index, queue_family)| QueueFamilyInfo {
+                index: index as u32,
+                queue_count: queue_family.queue_count,
+                flags: flag_names(queue_family.queue_flags),
+                timestamp_valid_bits: queue_family.timestamp_valid_bits,
+            })
+            .collect();
+    let memory_properties =
+        unsafe { instance_raw.get_physical_device_memory_properties(physical_device) };
+    let (memory_heaps, memory_types) = memory_info(&memory_properties);
+    let formats = REPORTED_FORMATS
+        .iter()
+        .map(|format| {
+            let format_properties = unsafe {
+                instance_raw.get_physical_device_format_properties(physical_device, *format)
+            };
+            FormatInfo {
+                format: format!("{:?}", format),
+                linear_tiling_features: flag_names(format_properties.linear_tiling_features),
+                optimal_tiling_features: flag_names(format_properties.optimal_tiling_features),
+                buffer_features: flag_names(format_properties.buffer_features),
+            }
+        })
+        .collect();
+    Ok(DeviceInfo {
+        index,
+        name: c_chars_to_string(&properties.device_name),
+        device_type: format!("{:?}", properties.device_type),
+        api_version: format_version(properties.api_version),
+        driver_version: properties.driver_version,
+        vendor_id: properties.vendor_id,
+        device_id: properties.device_id,
+        rejection,
+        extensions,
+        queue_families,
+        memory_heaps,
+        memory_types,
+        limits: limits_info(&properties.limits),
+        formats,
+    })
+}
+
+fn memory_info(
+    memory_properties: &PhysicalDeviceMemoryProperties,
+) -> (Vec<MemoryHeapInfo>, Vec<MemoryTypeInfo>) {
+    let heaps = memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize]
+        .iter()
+        .enumerate()
+        .map(|(index, heap)| MemoryHeapInfo {
+            index: index as u32,
+            size: heap.size,
+            flags: flag_names(heap.flags),
+        })
+        .collect();
+    let types = memory_properties.memory_types[..memory_properties.memory_type_count as usize]
+        .iter()
+        .enumerate()
+        .map(|(index, memory_type)| MemoryTypeInfo {
+            index: index as u32,
+            heap_index: memory_type.heap_index,
+            flags: flag_names(memory_type.property_flags),
+        })
+        .collect();
+    (heaps, types)
+}
+
+fn limits_info(limits: &PhysicalDeviceLimits) -> LimitsInfo {
+    LimitsInfo {
+        max_image_dimension_2d: limits.max_image_dimension2_d,
+        max_framebuffer_width: limits.max_framebuffer_width,
+        max_framebuffer_height: limits.max_framebuffer_height,
+        max_viewports: limits.max_viewports,
+        max_push_constants_size: limits.max_push_constants_size,
+        max_memory_allocation_count: limits.max_memory_allocation_count,
+        max_sampler_allocation_count: limits.max_sampler_allocation_count,
+        max_bound_descriptor_sets: limits.max_bound_descriptor_sets,
+        max_per_stage_descriptor_samplers: limits.max_per_stage_descriptor_samplers,
+        max_per_stage_descriptor_uniform_buffers: limits.max_per_stage_descriptor_uniform_buffers,
+        max_uniform_buffer_range: limits.max_uniform_buffer_range,
+        max_vertex_input_attributes: limits.max_vertex_input_attributes,
+        max_vertex_input_bindings: limits.max_vertex_input_bindings,
+        max_sampler_anisotropy: limits.max_sampler_anisotropy,
+        min_uniform_buffer_offset_alignment: limits.min_uniform_buffer_offset_alignment,
+        optimal_buffer_copy_row_pitch_alignment: limits.optimal_buffer_copy_row_pitch_alignment,
+        non_coherent_atom_size: limits.non_coherent_atom_size,
+        buffer_image_granularity: limits.buffer_image_granularity,
+        timestamp_period: limits.timestamp_period,
+    }
+}
+
+fn extension_info(extension: &ExtensionProperties) -> ExtensionInfo {
+    ExtensionInfo {
+        name: c_chars_to_string(&extension.extension_name),
+        spec_version: extension.spec_version,
+    }
+}
+
+/// `ash` のフラグの `Debug` 表現 (`A | B`) をフラグ名の配列にする
+fn flag_names<Flags: Debug>(flags: Flags) -> Vec<String> {
+    format!("{:?}", flags)
+        .split(" | ")
+        .filter(|name| !name.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+fn format_version(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        version_major(version),
+        version_minor(version),
+        version_patch(version)
+    )
+}
+
+fn c_chars_to_string(chars: &[c_char]) -> String {
+    unsafe { CStr::from_ptr(chars.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
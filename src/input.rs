@@ -0,0 +1,93 @@
+//! 入力関連
+
+pub mod action;
+pub mod gamepad;
+
+use glfw::WindowEvent;
+pub use glfw::{GamepadAxis, GamepadButton, JoystickId, Key, MouseButton};
+
+/// キーやマウスボタンの状態の変化
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonAction {
+    Press,
+    Release,
+    /// キーが押され続けていることによるリピート
+    Repeat,
+}
+
+impl From<glfw::Action> for ButtonAction {
+    fn from(action: glfw::Action) -> Self {
+        match action {
+            glfw::Action::Press => ButtonAction::Press,
+            glfw::Action::Release => ButtonAction::Release,
+            glfw::Action::Repeat => ButtonAction::Repeat,
+        }
+    }
+}
+
+/// ウィンドウやゲームパッドから届いたイベント
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    Key {
+        key: Key,
+        action: ButtonAction,
+    },
+    MouseButton {
+        button: MouseButton,
+        action: ButtonAction,
+    },
+    /// ウィンドウの左上を原点とするカーソルの座標
+    CursorMoved {
+        x: f64,
+        y: f64,
+    },
+    Scroll {
+        x: f64,
+        y: f64,
+    },
+    Focused(bool),
+    CloseRequested,
+    /// フレームバッファのサイズ (ピクセル単位) が変わった
+    Resized {
+        width: u32,
+        height: u32,
+    },
+    GamepadConnected(JoystickId),
+    GamepadDisconnected(JoystickId),
+    GamepadButton {
+        id: JoystickId,
+        button: GamepadButton,
+        action: ButtonAction,
+    },
+    /// デッドゾーンを適用した後の軸の値 (スティックは -1.0 〜 1.0、トリガーは 0.0 〜 1.0)
+    GamepadAxis {
+        id: JoystickId,
+        axis: GamepadAxis,
+        value: f32,
+    },
+}
+
+impl Event {
+    /// このゲームで扱わないイベントの場合は `None` を返す
+    pub(crate) fn from_glfw(event: WindowEvent) -> Option<Event> {
+        match event {
+            WindowEvent::Key(key, _, action, _) => Some(Event::Key {
+                key,
+                action: action.into(),
+            }),
+            WindowEvent::MouseButton(button, action, _) => Some(Event::MouseButton {
+                button,
+                action: action.into(),
+            }),
+            WindowEvent::CursorPos(x, y) => Some(Event::CursorMoved { x, y }),
+            WindowEvent::Scroll(x, y) => Some(Event::Scroll { x, y }),
+            WindowEvent::Focus(focused) => Some(Event::Focused(focused)),
+            WindowEvent::Close => Some(Event::CloseRequested),
+            WindowEvent::FramebufferSize(width, height) => Some(Event::Resized {
+                width: width.max(0) as u32,
+                height: height.max(0) as u32,
+            }),
+            _ => None,
+        }
+    }
+}
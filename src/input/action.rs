@@ -0,0 +1,375 @@
+//! キー割り当てを差し替えられるアクション単位の入力
+//!
+//! ゲームのコードは `glfw::Key` を直接見る代わりに、アクションが押されているかどうかを問い合わせる。
+//! キー割り当ては次のような TOML ファイルから読み込む。
+//!
+//! ```toml
+//! [buttons]
+//! Jump = ["Space", "Mouse1", "GamepadA"]
+//!
+//! [axes]
+//! MoveX = [
+//!     { negative = "A", positive = "D" },
+//!     { negative = "Left", positive = "Right" },
+//!     { gamepad = "LeftX" },
+//! ]
+//! MoveY = [{ gamepad = "LeftY", invert = true }]
+//! ```
+
+use super::{ButtonAction, Event, GamepadAxis, GamepadButton, JoystickId, Key, MouseButton};
+use anyhow::Context;
+use serde::{
+    de::{value::StrDeserializer, DeserializeOwned, IntoDeserializer},
+    Deserialize, Deserializer,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    hash::Hash,
+    path::Path,
+};
+
+/// アクションに割り当てられる物理的な入力
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Binding {
+    Key(Key),
+    MouseButton(MouseButton),
+    /// どのゲームパッドのボタンでもよい
+    GamepadButton(GamepadButton),
+}
+
+impl TryFrom<String> for Binding {
+    type Error = anyhow::Error;
+
+    /// キーは `glfw::Key` のバリアント名 (`"Space"`, `"A"`, `"Num1"` など) で、
+    /// マウスボタンは `"Mouse1"` から `"Mouse8"` で、
+    /// ゲームパッドのボタンは `"GamepadA"` や `"GamepadDpadUp"` などで表す
+    fn try_from(name: String) -> anyhow::Result<Self> {
+        if let Some(button) = parse_mouse_button(&name) {
+            return Ok(Binding::MouseButton(button));
+        }
+        if let Some(button) = parse_gamepad_button(&name) {
+            return Ok(Binding::GamepadButton(button));
+        }
+        parse_key(&name)
+            .map(Binding::Key)
+            .with_context(|| format!("Unknown key or button name: {}", name))
+    }
+}
+
+/// -1.0 〜 1.0 の値を作るアナログ軸の割り当て
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum AxisBinding {
+    /// 2 つの入力の片方が押されていれば -1.0 か 1.0 になる
+    Buttons {
+        negative: Binding,
+        positive: Binding,
+    },
+    /// ゲームパッドの軸 (`"LeftX"`, `"LeftY"`, `"RightX"`, `"RightY"`, `"LeftTrigger"`, `"RightTrigger"`)
+    Gamepad {
+        #[serde(deserialize_with = "deserialize_gamepad_axis")]
+        gamepad: GamepadAxis,
+        /// スティックの Y 軸は下が正なので、上を正にしたいときは反転させる
+        #[serde(default)]
+        invert: bool,
+    },
+}
+
+/// アクションごとの割り当て
+#[derive(Clone, Debug)]
+pub struct Bindings<A> {
+    pub buttons: HashMap<A, Vec<Binding>>,
+    pub axes: HashMap<A, Vec<AxisBinding>>,
+}
+
+/// TOML のテーブルのキーは文字列としてしか読めないので、一度この形で読み込んでからアクションに変換する
+#[derive(Deserialize)]
+struct RawBindings {
+    #[serde(default)]
+    buttons: HashMap<String, Vec<Binding>>,
+    #[serde(default)]
+    axes: HashMap<String, Vec<AxisBinding>>,
+}
+
+impl<A> Bindings<A>
+where
+    A: DeserializeOwned + Eq + Hash,
+{
+    pub fn from_toml(source: &str) -> anyhow::Result<Bindings<A>> {
+        let raw: RawBindings = toml::from_str(source).context("Failed to parse key bindings")?;
+        Ok(Bindings {
+            buttons: parse_actions(raw.buttons)?,
+            axes: parse_actions(raw.axes)?,
+        })
+    }
+
+    pub fn load<P>(path: P) -> anyhow::Result<Bindings<A>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read key bindings from {}", path.display()))?;
+        Bindings::from_toml(&source)
+            .with_context(|| format!("Invalid key bindings in {}", path.display()))
+    }
+}
+
+/// どの装置の入力か (キーボードとマウスは `None`)
+type Input = (Binding, Option<JoystickId>);
+
+/// ウィンドウやゲームパッドのイベントを受け取って、アクションの状態をフレーム単位で管理する
+pub struct ActionMap<A> {
+    bindings: Bindings<A>,
+    /// 押されている入力
+    down: HashSet<Input>,
+    /// このフレームで押された入力
+    pressed: HashSet<Input>,
+    /// このフレームで離された入力
+    released: HashSet<Input>,
+    /// ゲームパッドの軸の値
+    gamepad_axes: HashMap<(JoystickId, GamepadAxis), f32>,
+}
+
+impl<A> ActionMap<A>
+where
+    A: Eq + Hash,
+{
+    pub fn new(bindings: Bindings<A>) -> ActionMap<A> {
+        ActionMap {
+            bindings,
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            gamepad_axes: HashMap::new(),
+        }
+    }
+
+    /// キー割り当てを差し替える (押されている入力はそのまま引き継ぐ)
+    pub fn set_bindings(&mut self, bindings: Bindings<A>) {
+        self.bindings = bindings;
+    }
+
+    /// フレームの始めに呼び、前のフレームで押された・離された入力を忘れる
+    pub fn begin_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        let (input, action) = match *event {
+            Event::Key { key, action } => ((Binding::Key(key), None), action),
+            Event::MouseButton { button, action } => ((Binding::MouseButton(button), None), action),
+            Event::GamepadButton { id, button, action } => {
+                ((Binding::GamepadButton(button), Some(id)), action)
+            }
+            Event::GamepadAxis { id, axis, value } => {
+                self.gamepad_axes.insert((id, axis), value);
+                return;
+            }
+            Event::GamepadDisconnected(id) => {
+                self.gamepad_axes.retain(|(axis_id, _), _| *axis_id != id);
+                return;
+            }
+            // フォーカスを失うと離したイベントが届かないので、キーやマウスボタンが押しっぱなしにならないようにする
+            Event::Focused(false) => {
+                let keyboard_and_mouse = self
+                    .down
+                    .iter()
+                    .filter(|(_, id)| id.is_none())
+                    .copied()
+                    .collect::<Vec<_>>();
+                for input in keyboard_and_mouse {
+                    self.down.remove(&input);
+                    self.released.insert(input);
+                }
+                return;
+            }
+            _ => return,
+        };
+        match action {
+            ButtonAction::Press => {
+                if self.down.insert(input) {
+                    self.pressed.insert(input);
+                }
+            }
+            ButtonAction::Release => {
+                if self.down.remove(&input) {
+                    self.released.insert(input);
+                }
+            }
+            ButtonAction::Repeat => {}
+        }
+    }
+
+    /// 割り当てられた入力のどれかが押されているかどうか
+    pub fn is_pressed(&self, action: A) -> bool {
+        self.any_binding_in(&action, &self.down)
+    }
+
+    /// このフレームで押され始めたかどうか
+    pub fn is_just_pressed(&self, action: A) -> bool {
+        self.any_binding_in(&action, &self.pressed)
+    }
+
+    /// このフレームで割り当てられた入力がすべて離されたかどうか
+    pub fn is_just_released(&self, action: A) -> bool {
+        !self.any_binding_in(&action, &self.down) && self.any_binding_in(&action, &self.released)
+    }
+
+    /// -1.0 〜 1.0 の軸の値 (割り当てが複数あれば足し合わせる)
+    pub fn axis(&self, action: A) -> f32 {
+        let value: f32 = self
+            .bindings
+            .axes
+            .get(&action)
+            .map(|axes| {
+                axes.iter()
+                    .map(|axis| match *axis {
+                        AxisBinding::Buttons { negative, positive } => {
+                            let negative = self.is_down(negative) as i32 as f32;
+                            let positive = self.is_down(positive) as i32 as f32;
+                            positive - negative
+                        }
+                        AxisBinding::Gamepad { gamepad, invert } => {
+                            let value = self.gamepad_axis(gamepad);
+                            if invert {
+                                -value
+                            } else {
+                                value
+                            }
+                        }
+                    })
+                    .sum()
+            })
+            .unwrap_or(0.0);
+        value.clamp(-1.0, 1.0)
+    }
+
+    fn any_binding_in(&self, action: &A, inputs: &HashSet<Input>) -> bool {
+        self.bindings
+            .buttons
+            .get(action)
+            .map(|bindings| inputs.iter().any(|(binding, _)| bindings.contains(binding)))
+            .unwrap_or(false)
+    }
+
+    fn is_down(&self, binding: Binding) -> bool {
+        self.down.iter().any(|(down, _)| *down == binding)
+    }
+
+    /// 接続されているゲームパッドのうち、最も大きく傾けられているものの値
+    fn gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        self.gamepad_axes
+            .iter()
+            .filter(|((_, gamepad_axis), _)| *gamepad_axis == axis)
+            .map(|(_, value)| *value)
+            .fold(
+                0.0,
+                |max, value| {
+                    if value.abs() > max.abs() {
+                        value
+                    } else {
+                        max
+                    }
+                },
+            )
+    }
+}
+
+fn parse_actions<A, T>(raw: HashMap<String, T>) -> anyhow::Result<HashMap<A, T>>
+where
+    A: DeserializeOwned + Eq + Hash,
+{
+    raw.into_iter()
+        .map(|(name, value)| {
+            let deserializer: StrDeserializer<serde::de::value::Error> =
+                name.as_str().into_deserializer();
+            let action = A::deserialize(deserializer)
+                .with_context(|| format!("Unknown action: {}", name))?;
+            Ok((action, value))
+        })
+        .collect()
+}
+
+fn deserialize_gamepad_axis<'de, D>(deserializer: D) -> Result<GamepadAxis, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+    match name.as_str() {
+        "LeftX" => Ok(GamepadAxis::AxisLeftX),
+        "LeftY" => Ok(GamepadAxis::AxisLeftY),
+        "RightX" => Ok(GamepadAxis::AxisRightX),
+        "RightY" => Ok(GamepadAxis::AxisRightY),
+        "LeftTrigger" => Ok(GamepadAxis::AxisLeftTrigger),
+        "RightTrigger" => Ok(GamepadAxis::AxisRightTrigger),
+        _ => Err(serde::de::Error::custom(format!(
+            "Unknown gamepad axis: {}",
+            name
+        ))),
+    }
+}
+
+fn parse_gamepad_button(name: &str) -> Option<GamepadButton> {
+    match name {
+        "GamepadA" => Some(GamepadButton::ButtonA),
+        "GamepadB" => Some(GamepadButton::ButtonB),
+        "GamepadX" => Some(GamepadButton::ButtonX),
+        "GamepadY" => Some(GamepadButton::ButtonY),
+        "GamepadLeftBumper" => Some(GamepadButton::ButtonLeftBumper),
+        "GamepadRightBumper" => Some(GamepadButton::ButtonRightBumper),
+        "GamepadBack" => Some(GamepadButton::ButtonBack),
+        "GamepadStart" => Some(GamepadButton::ButtonStart),
+        "GamepadGuide" => Some(GamepadButton::ButtonGuide),
+        "GamepadLeftThumb" => Some(GamepadButton::ButtonLeftThumb),
+        "GamepadRightThumb" => Some(GamepadButton::ButtonRightThumb),
+        "GamepadDpadUp" => Some(GamepadButton::ButtonDpadUp),
+        "GamepadDpadRight" => Some(GamepadButton::ButtonDpadRight),
+        "GamepadDpadDown" => Some(GamepadButton::ButtonDpadDown),
+        "GamepadDpadLeft" => Some(GamepadButton::ButtonDpadLeft),
+        _ => None,
+    }
+}
+
+fn parse_mouse_button(name: &str) -> Option<MouseButton> {
+    match name {
+        "Mouse1" => Some(MouseButton::Button1),
+        "Mouse2" => Some(MouseButton::Button2),
+        "Mouse3" => Some(MouseButton::Button3),
+        "Mouse4" => Some(MouseButton::Button4),
+        "Mouse5" => Some(MouseButton::Button5),
+        "Mouse6" => Some(MouseButton::Button6),
+        "Mouse7" => Some(MouseButton::Button7),
+        "Mouse8" => Some(MouseButton::Button8),
+        _ => None,
+    }
+}
+
+macro_rules! key_names {
+    ($($name:ident),* $(,)?) => {
+        fn parse_key(name: &str) -> Option<Key> {
+            match name {
+                $(stringify!($name) => Some(Key::$name),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+key_names! {
+    Space, Apostrophe, Comma, Minus, Period, Slash, Semicolon, Equal,
+    Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    LeftBracket, Backslash, RightBracket, GraveAccent,
+    Escape, Enter, Tab, Backspace, Insert, Delete,
+    Right, Left, Down, Up, PageUp, PageDown, Home, End,
+    CapsLock, ScrollLock, NumLock, PrintScreen, Pause,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Kp0, Kp1, Kp2, Kp3, Kp4, Kp5, Kp6, Kp7, Kp8, Kp9,
+    KpDecimal, KpDivide, KpMultiply, KpSubtract, KpAdd, KpEnter, KpEqual,
+    LeftShift, LeftControl, LeftAlt, LeftSuper,
+    RightShift, RightControl, RightAlt, RightSuper, Menu,
+}
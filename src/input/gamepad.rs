@@ -0,0 +1,229 @@
+//! GLFW のゲームパッド API を使った入力
+//!
+//! GLFW の接続コールバックはプロセス全体で 1 つしか登録できないので、
+//! 毎フレーム接続状態を調べて、変化をイベントとして返す
+
+use super::{ButtonAction, Event, GamepadAxis, GamepadButton, JoystickId};
+use glfw::{Action, Glfw};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const JOYSTICK_IDS: [JoystickId; 16] = [
+    JoystickId::Joystick1,
+    JoystickId::Joystick2,
+    JoystickId::Joystick3,
+    JoystickId::Joystick4,
+    JoystickId::Joystick5,
+    JoystickId::Joystick6,
+    JoystickId::Joystick7,
+    JoystickId::Joystick8,
+    JoystickId::Joystick9,
+    JoystickId::Joystick10,
+    JoystickId::Joystick11,
+    JoystickId::Joystick12,
+    JoystickId::Joystick13,
+    JoystickId::Joystick14,
+    JoystickId::Joystick15,
+    JoystickId::Joystick16,
+];
+
+pub const GAMEPAD_BUTTONS: [GamepadButton; 15] = [
+    GamepadButton::ButtonA,
+    GamepadButton::ButtonB,
+    GamepadButton::ButtonX,
+    GamepadButton::ButtonY,
+    GamepadButton::ButtonLeftBumper,
+    GamepadButton::ButtonRightBumper,
+    GamepadButton::ButtonBack,
+    GamepadButton::ButtonStart,
+    GamepadButton::ButtonGuide,
+    GamepadButton::ButtonLeftThumb,
+    GamepadButton::ButtonRightThumb,
+    GamepadButton::ButtonDpadUp,
+    GamepadButton::ButtonDpadRight,
+    GamepadButton::ButtonDpadDown,
+    GamepadButton::ButtonDpadLeft,
+];
+
+pub const GAMEPAD_AXES: [GamepadAxis; 6] = [
+    GamepadAxis::AxisLeftX,
+    GamepadAxis::AxisLeftY,
+    GamepadAxis::AxisRightX,
+    GamepadAxis::AxisRightY,
+    GamepadAxis::AxisLeftTrigger,
+    GamepadAxis::AxisRightTrigger,
+];
+
+/// 入力を無視する範囲
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Deadzones {
+    /// スティックの傾きの大きさ (0.0 〜 1.0) に対する閾値
+    pub stick: f32,
+    /// トリガーの押し込み量 (0.0 〜 1.0) に対する閾値
+    pub trigger: f32,
+}
+
+impl Default for Deadzones {
+    fn default() -> Self {
+        Deadzones {
+            stick: 0.15,
+            trigger: 0.05,
+        }
+    }
+}
+
+impl Deadzones {
+    /// スティックの 2 軸の値に円形のデッドゾーンを適用し、閾値の外側を 0.0 〜 1.0 に引き伸ばす
+    pub fn apply_to_stick(&self, x: f32, y: f32) -> (f32, f32) {
+        let magnitude = (x * x + y * y).sqrt();
+        if magnitude <= self.stick {
+            return (0.0, 0.0);
+        }
+        let scale = ((magnitude - self.stick) / (1.0 - self.stick)).min(1.0) / magnitude;
+        (x * scale, y * scale)
+    }
+
+    /// GLFW のトリガーの値 (-1.0 〜 1.0) を 0.0 〜 1.0 にしてからデッドゾーンを適用する
+    pub fn apply_to_trigger(&self, value: f32) -> f32 {
+        let value = (value + 1.0) / 2.0;
+        if value <= self.trigger {
+            0.0
+        } else {
+            ((value - self.trigger) / (1.0 - self.trigger)).min(1.0)
+        }
+    }
+}
+
+/// 1 台のゲームパッドについて、前回読み取った状態
+#[derive(Clone, Copy, Default)]
+struct PadState {
+    buttons: [bool; GAMEPAD_BUTTONS.len()],
+    axes: [f32; GAMEPAD_AXES.len()],
+}
+
+/// 接続されているゲームパッドの状態を追跡し、変化をイベントとして返す
+pub struct Gamepads {
+    glfw_raw: Glfw,
+    deadzones: Deadzones,
+    pads: HashMap<JoystickId, PadState>,
+}
+
+impl Gamepads {
+    pub fn new(glfw_raw: Glfw) -> Gamepads {
+        Gamepads {
+            glfw_raw,
+            deadzones: Deadzones::default(),
+            pads: HashMap::new(),
+        }
+    }
+
+    pub fn set_deadzones(&mut self, deadzones: Deadzones) {
+        self.deadzones = deadzones;
+    }
+
+    /// 接続されているゲームパッドの ID
+    pub fn connected(&self) -> impl Iterator<Item = JoystickId> + '_ {
+        self.pads.keys().copied()
+    }
+
+    /// 前回の呼び出しからの変化をイベントとして返す
+    ///
+    /// 切断されたゲームパッドについては、押されていたボタンを離すイベントと軸を 0 に戻すイベントを先に返す
+    pub fn poll(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        for id in JOYSTICK_IDS.iter().copied() {
+            let joystick = self.glfw_raw.get_joystick(id);
+            // マッピングが無いジョイスティックはボタンの意味が分からないので扱わない
+            let state = if joystick.is_present() && joystick.is_gamepad() {
+                joystick.get_gamepad_state()
+            } else {
+                None
+            };
+            match (state, self.pads.contains_key(&id)) {
+                (Some(state), connected) => {
+                    if !connected {
+                        info!(
+                            "Gamepad {:?} was connected: {}",
+                            id,
+                            joystick
+                                .get_gamepad_name()
+                                .unwrap_or_else(|| "(unknown)".to_string())
+                        );
+                        events.push(Event::GamepadConnected(id));
+                    }
+                    let current = self.read_state(&state);
+                    let previous = self.pads.insert(id, current).unwrap_or_default();
+                    push_changes(&mut events, id, &previous, &current);
+                }
+                (None, true) => {
+                    info!("Gamepad {:?} was disconnected", id);
+                    let previous = self.pads.remove(&id).unwrap_or_default();
+                    push_changes(&mut events, id, &previous, &PadState::default());
+                    events.push(Event::GamepadDisconnected(id));
+                }
+                (None, false) => {}
+            }
+        }
+        events
+    }
+
+    fn read_state(&self, state: &glfw::GamepadState) -> PadState {
+        let mut buttons = [false; GAMEPAD_BUTTONS.len()];
+        for (pressed, button) in buttons.iter_mut().zip(GAMEPAD_BUTTONS.iter()) {
+            *pressed = state.get_button_state(*button) == Action::Press;
+        }
+        let (left_x, left_y) = self.deadzones.apply_to_stick(
+            state.get_axis(GamepadAxis::AxisLeftX),
+            state.get_axis(GamepadAxis::AxisLeftY),
+        );
+        let (right_x, right_y) = self.deadzones.apply_to_stick(
+            state.get_axis(GamepadAxis::AxisRightX),
+            state.get_axis(GamepadAxis::AxisRightY),
+        );
+        let left_trigger = self
+            .deadzones
+            .apply_to_trigger(state.get_axis(GamepadAxis::AxisLeftTrigger));
+        let right_trigger = self
+            .deadzones
+            .apply_to_trigger(state.get_axis(GamepadAxis::AxisRightTrigger));
+        PadState {
+            buttons,
+            // GAMEPAD_AXES と同じ順番
+            axes: [
+                left_x,
+                left_y,
+                right_x,
+                right_y,
+                left_trigger,
+                right_trigger,
+            ],
+        }
+    }
+}
+
+fn push_changes(events: &mut Vec<Event>, id: JoystickId, previous: &PadState, current: &PadState) {
+    for (index, button) in GAMEPAD_BUTTONS.iter().enumerate() {
+        if previous.buttons[index] != current.buttons[index] {
+            let action = if current.buttons[index] {
+                ButtonAction::Press
+            } else {
+                ButtonAction::Release
+            };
+            events.push(Event::GamepadButton {
+                id,
+                button: *button,
+                action,
+            });
+        }
+    }
+    for (index, axis) in GAMEPAD_AXES.iter().enumerate() {
+        if previous.axes[index] != current.axes[index] {
+            events.push(Event::GamepadAxis {
+                id,
+                axis: *axis,
+                value: current.axes[index],
+            });
+        }
+    }
+}
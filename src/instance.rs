@@ -1,37 +1,56 @@
 //! Vulkan インスタンス関連
 
 use crate::{
-    glfw_wrapper::GlfwWrapper, logical_device::ManagedLogicalDevice, window::ManagedWindow,
+    debug_messenger::{DebugMessenger, DebugState},
+    glfw_wrapper::GlfwWrapper,
+    logical_device::ManagedLogicalDevice,
+    physical_device::{self, DeviceCandidate, DeviceSelection},
+    pipeline_cache::{self, ManagedPipelineCache},
+    window::ManagedWindow,
 };
 use anyhow::Context;
 use ash::{
-    extensions::khr::{Surface, Swapchain},
-    version::{EntryV1_0, InstanceV1_0},
+    extensions::{
+        ext::DebugUtils,
+        khr::{Surface, Swapchain},
+    },
+    version::{DeviceV1_0, EntryV1_0, InstanceV1_0},
     vk::{
         make_version, ApplicationInfo, DeviceCreateInfo, DeviceQueueCreateInfo, Handle,
-        InstanceCreateInfo, PhysicalDevice, PhysicalDeviceFeatures, QueueFamilyProperties,
-        QueueFlags, SurfaceKHR,
+        InstanceCreateInfo, SurfaceKHR,
     },
     Entry, Instance,
 };
 use once_cell::sync::Lazy;
 use std::ffi::CStr;
-use std::{ffi::CString, os::raw::c_char};
+use std::{ffi::CString, os::raw::c_char, path::PathBuf};
 
 /// 自動で解放される、Vulkan インスタンスのラッパー
 pub struct ManagedInstance<'a> {
     entry: &'a Entry,
-    glfw: &'a GlfwWrapper,
+    glfw: Option<&'a GlfwWrapper>,
     instance_raw: Instance,
+    /// 実際に有効にしたレイヤ (論理デバイスにも同じものを渡す)
+    enabled_layers: Vec<CString>,
+    /// バリデーションレイヤが無効なら `None`
+    debug_messenger: Option<DebugMessenger>,
+    /// コールバックにポインタを渡しているので、インスタンスを破棄し終わるまで持っておく
+    debug_state: Box<DebugState>,
+    device_selection: DeviceSelection,
+    /// `None` ならパイプラインキャッシュをファイルに保存しない
+    pipeline_cache_path: Option<PathBuf>,
 }
 
 static VALIDATION_LAYERS: Lazy<Vec<CString>> =
     Lazy::new(|| vec![CString::new("VK_LAYER_KHRONOS_validation").unwrap()]);
 
 impl<'a> ManagedInstance<'a> {
+    /// `glfw` に `None` を渡すと、ウィンドウを作らないヘッドレス用のインスタンスになる
+    ///
+    /// バリデーションレイヤがインストールされていない場合は、警告を出して無効のまま続行する
     pub fn new(
         entry: &'a Entry,
-        glfw: &'a GlfwWrapper,
+        glfw: Option<&'a GlfwWrapper>,
         with_validation_layers: bool,
     ) -> anyhow::Result<ManagedInstance<'a>> {
         let application_name = CString::new("Game")?;
@@ -42,42 +61,106 @@ impl<'a> ManagedInstance<'a> {
             .engine_name(engine_name.as_c_str())
             .build();
 
-        let enabled_layer_names = if with_validation_layers {
-            debug!("Validation layers: enabled");
-            (*VALIDATION_LAYERS)
-                .iter()
-                .map(|name| name.as_ptr())
-                .collect()
+        let enabled_layers = if with_validation_layers {
+            filter_available_layers(entry, &VALIDATION_LAYERS)?
         } else {
-            debug!("Validation layers: disabled");
             Vec::new()
         };
+        let with_validation_layers = !enabled_layers.is_empty();
+        if with_validation_layers {
+            debug!("Validation layers: enabled");
+        } else {
+            debug!("Validation layers: disabled");
+        }
+        let enabled_layer_names: Vec<*const c_char> =
+            enabled_layers.iter().map(|name| name.as_ptr()).collect();
 
-        let enabled_extension_names: Vec<CString> = glfw
-            .get_required_instance_extensions()?
-            .iter()
-            .map(|item| CString::new(item.as_str()).unwrap())
-            .collect();
+        let mut enabled_extension_names: Vec<CString> = match glfw {
+            Some(glfw) => glfw
+                .get_required_instance_extensions()?
+                .iter()
+                .map(|item| CString::new(item.as_str()).unwrap())
+                .collect(),
+            None => Vec::new(),
+        };
+        if with_validation_layers {
+            enabled_extension_names.push(DebugUtils::name().to_owned());
+        }
         let enabled_extension_names: Vec<*const c_char> = enabled_extension_names
             .iter()
             .map(|item| item.as_ptr())
             .collect();
 
-        let create_info = InstanceCreateInfo::builder()
+        let debug_state = Box::new(DebugState::default());
+        let mut debug_create_info = DebugMessenger::create_info(&debug_state);
+        let mut create_info = InstanceCreateInfo::builder()
             .application_info(&app_info)
             .enabled_extension_names(&enabled_extension_names)
-            .enabled_layer_names(&enabled_layer_names)
-            .build();
+            .enabled_layer_names(&enabled_layer_names);
+        if with_validation_layers {
+            create_info = create_info.push_next(&mut debug_create_info);
+        }
+        let create_info = create_info.build();
         let instance_raw = unsafe { entry.create_instance(&create_info, None) }
             .context("Failed to create Vulkan instance")?;
 
+        let debug_messenger = if with_validation_layers {
+            match DebugMessenger::new(entry, &instance_raw, &debug_state) {
+                Ok(debug_messenger) => Some(debug_messenger),
+                Err(err) => {
+                    unsafe { instance_raw.destroy_instance(None) };
+                    return Err(err);
+                }
+            }
+        } else {
+            None
+        };
+
         Ok(ManagedInstance {
             entry,
             glfw,
             instance_raw,
+            enabled_layers,
+            debug_messenger,
+            debug_state,
+            device_selection: DeviceSelection::from_env(),
+            pipeline_cache_path: pipeline_cache::default_path(),
         })
     }
 
+    /// バリデーションエラーが報告されていたら、インスタンスの破棄時にパニックするようにする (テスト用)
+    pub fn set_panic_on_validation_error(&self, enabled: bool) {
+        self.debug_state.set_panic_on_error(enabled);
+    }
+
+    /// 論理デバイスを作るときに使う物理デバイスを指定する (初期値は環境変数 `GAME_DEVICE` に従う)
+    pub fn set_device_selection(&mut self, selection: DeviceSelection) {
+        self.device_selection = selection;
+    }
+
+    /// パイプラインキャッシュを読み書きするファイルを指定する
+    ///
+    /// 初期値は環境変数 `GAME_PIPELINE_CACHE` か、ユーザのキャッシュディレクトリの中。`None` なら保存しない
+    pub fn set_pipeline_cache_path(&mut self, path: Option<PathBuf>) {
+        self.pipeline_cache_path = path;
+    }
+
+    pub(crate) fn get_entry(&self) -> &Entry {
+        self.entry
+    }
+
+    pub(crate) fn get_instance_raw(&self) -> &Instance {
+        &self.instance_raw
+    }
+
+    /// すべての物理デバイスと、それぞれが使えるかどうかを返す
+    pub fn list_physical_devices(
+        &self,
+        window: Option<&ManagedWindow>,
+    ) -> anyhow::Result<Vec<DeviceCandidate>> {
+        physical_device::enumerate_candidates(&self.instance_raw, window)
+    }
+
     pub fn create_window<Title>(
         &self,
         width: u32,
@@ -87,7 +170,10 @@ impl<'a> ManagedInstance<'a> {
     where
         Title: ToString,
     {
-        let window_raw = self.glfw.create_window_raw(width, height, title)?;
+        let glfw = self
+            .glfw
+            .context("Cannot create a window from a headless instance")?;
+        let (window_raw, event_receiver) = glfw.create_window_raw(width, height, title)?;
 
         let surface_loader = Surface::new(self.entry, &self.instance_raw);
 
@@ -99,23 +185,43 @@ impl<'a> ManagedInstance<'a> {
         );
         let surface = SurfaceKHR::from_raw(surface_raw);
 
-        Ok(ManagedWindow::new(window_raw, surface_loader, surface))
+        Ok(ManagedWindow::new(
+            window_raw,
+            event_receiver,
+            surface_loader,
+            surface,
+        ))
     }
 
     pub fn create_logical_device(
         &self,
         window: Option<&ManagedWindow>,
     ) -> anyhow::Result<ManagedLogicalDevice> {
-        let (physical_device, queue_indices) =
-            unsafe { self.instance_raw.enumerate_physical_devices() }
-                .context("Failed to enumerate physical deviuces")?
-                .into_iter()
-                .find_map(|physical_device| {
-                    try_get_queue_family_indices(physical_device, &self.instance_raw, window)
-                })
-                .context("No suitable physical device")?;
+        let candidates = self.list_physical_devices(window)?;
+        for candidate in &candidates {
+            match &candidate.rejection {
+                None => debug!(
+                    "Physical device #{} ({}): {:?}",
+                    candidate.index, candidate.name, candidate.score
+                ),
+                Some(rejection) => debug!(
+                    "Physical device #{} ({}): rejected: {}",
+                    candidate.index, candidate.name, rejection
+                ),
+            }
+        }
+        let selected = physical_device::select_candidate(&candidates, &self.device_selection)?;
+        info!(
+            "Using physical device #{} ({}, {:?})",
+            selected.index, selected.name, selected.device_type
+        );
+        let physical_device = selected.physical_device;
+        let queue_indices = selected
+            .queue_indices
+            .context("Selected physical device has no queue families")?;
 
         let queue_create_infos = queue_indices
+            .unique()
             .iter()
             .map(|index| {
                 DeviceQueueCreateInfo::builder()
@@ -124,14 +230,26 @@ impl<'a> ManagedInstance<'a> {
                     .build()
             })
             .collect::<Vec<_>>();
-        let device_features = PhysicalDeviceFeatures::builder().build();
-        let layer_name_ptrs: Vec<*const c_char> = (*VALIDATION_LAYERS)
+        let supported_features = unsafe {
+            self.instance_raw
+                .get_physical_device_features(physical_device)
+        };
+        let device_features = physical_device::features_to_enable(&supported_features);
+        // デバイスレイヤは非推奨だが、古い実装のためにインスタンスと同じものを渡しておく
+        let layer_name_ptrs: Vec<*const c_char> = self
+            .enabled_layers
             .iter()
             .map(|name| name.as_ptr())
             .collect();
+        // スワップチェーンはウィンドウへの表示にしか使わない
+        let extension_names = if window.is_some() {
+            vec![Swapchain::name().as_ptr()]
+        } else {
+            Vec::new()
+        };
         let device_create_info = DeviceCreateInfo::builder()
             .queue_create_infos(&queue_create_infos)
-            .enabled_extension_names(&[Swapchain::name().as_ptr()])
+            .enabled_extension_names(&extension_names)
             .enabled_features(&device_features)
             .enabled_layer_names(&layer_name_ptrs)
             .build();
@@ -140,78 +258,72 @@ impl<'a> ManagedInstance<'a> {
                 .create_device(physical_device, &device_create_info, None)
         }
         .context("Failed to create logical device")?;
+        let properties = unsafe {
+            self.instance_raw
+                .get_physical_device_properties(physical_device)
+        };
+        let pipeline_cache = match ManagedPipelineCache::new(
+            device_raw.clone(),
+            &properties,
+            self.pipeline_cache_path.clone(),
+        ) {
+            Ok(pipeline_cache) => pipeline_cache,
+            Err(err) => {
+                unsafe { device_raw.destroy_device(None) };
+                return Err(err);
+            }
+        };
         Ok(ManagedLogicalDevice::new(
             &self.instance_raw,
             physical_device,
             device_raw,
             queue_indices,
+            device_features,
+            pipeline_cache,
         ))
     }
 }
 
 impl Drop for ManagedInstance<'_> {
     fn drop(&mut self) {
+        // メッセンジャーだけを先に破棄する
+        // `InstanceCreateInfo` に繋いだコールバックは破棄中も呼ばれるので、`debug_state` はまだ残しておく
+        drop(self.debug_messenger.take());
         unsafe { self.instance_raw.destroy_instance(None) }
-        trace!("Vulkan instance was destroyed")
-    }
-}
-
-fn try_get_queue_family_indices(
-    physical_device: PhysicalDevice,
-    instance_raw: &Instance,
-    window: Option<&ManagedWindow>,
-) -> Option<(PhysicalDevice, Vec<u32>)> {
-    let queue_families =
-        unsafe { instance_raw.get_physical_device_queue_family_properties(physical_device) };
-    if !check_swapchain_support(instance_raw, &physical_device) {
-        return None;
-    }
-    let graphics_queue_index = find_graphics_queue_family_index(&queue_families)?;
-    if let Some(window) = window {
-        let presentation_queue_index =
-            find_presentation_queue_family_index(&queue_families, &physical_device, window)?;
-        let mut queue_indices = vec![graphics_queue_index, presentation_queue_index];
-        queue_indices.dedup();
-        Some((physical_device, queue_indices))
-    } else {
-        Some((physical_device, vec![graphics_queue_index]))
+        trace!("Vulkan instance was destroyed");
+        // 破棄時に報告されたエラー (解放し忘れたオブジェクトなど) も含める
+        let validation_errors = if self.debug_state.panics_on_error() {
+            self.debug_state.take_errors()
+        } else {
+            Vec::new()
+        };
+        // 既にパニックしている最中に再びパニックするとアボートしてしまう
+        if !validation_errors.is_empty() && !std::thread::panicking() {
+            panic!(
+                "{} validation error(s) were reported:\n{}",
+                validation_errors.len(),
+                validation_errors.join("\n")
+            );
+        }
     }
 }
 
-fn find_graphics_queue_family_index(queue_families: &[QueueFamilyProperties]) -> Option<u32> {
-    queue_families
-        .iter()
-        .enumerate()
-        .find_map(|(queue_family_index, queue_family)| {
-            queue_family
-                .queue_flags
-                .contains(QueueFlags::GRAPHICS)
-                .then(|| queue_family_index as u32)
-        })
-}
-
-fn check_swapchain_support(instance_raw: &Instance, physical_device: &PhysicalDevice) -> bool {
-    unsafe { instance_raw.enumerate_device_extension_properties(*physical_device) }
-        .map(|exts| {
-            exts.into_iter().any(
-                |ext| unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) } == Swapchain::name(),
-            )
-        })
-        .unwrap_or(false)
-}
-
-fn find_presentation_queue_family_index(
-    queue_families: &[QueueFamilyProperties],
-    physical_device: &PhysicalDevice,
-    window: &ManagedWindow,
-) -> Option<u32> {
-    queue_families
+/// `requested` のうち、インストールされているレイヤだけを返す
+fn filter_available_layers(entry: &Entry, requested: &[CString]) -> anyhow::Result<Vec<CString>> {
+    let available = entry
+        .enumerate_instance_layer_properties()
+        .context("Failed to enumerate instance layer properties")?;
+    Ok(requested
         .iter()
-        .enumerate()
-        .find_map(|(queue_family_index, _)| {
-            let queue_family_index = queue_family_index as u32;
-            window
-                .get_physical_device_surface_support(&physical_device, queue_family_index)
-                .then(|| queue_family_index)
+        .filter(|name| {
+            let found = available.iter().any(|layer| {
+                name.as_c_str() == unsafe { CStr::from_ptr(layer.layer_name.as_ptr()) }
+            });
+            if !found {
+                warn!("Layer {:?} is not available, skipping it", name);
+            }
+            found
         })
+        .cloned()
+        .collect())
 }
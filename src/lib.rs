@@ -5,15 +5,32 @@ extern crate anyhow;
 #[macro_use]
 extern crate log;
 
+pub mod allocator;
+pub mod buffer;
 mod command_buffer;
 mod command_pool;
+pub mod config;
+mod debug_messenger;
+pub mod descriptor;
+mod frame;
 mod framebuffer;
 pub mod glfw_wrapper;
+pub mod headless;
+pub mod info;
+pub mod input;
 pub mod instance;
 mod linear_image;
 mod logical_device;
+pub mod math;
+pub mod mesh;
 mod optimized_image;
-mod pipeline;
+pub mod physical_device;
+pub mod pipeline;
+pub mod pipeline_cache;
 mod render_pass;
-mod shader;
+pub mod shader;
+mod swapchain;
+mod sync;
+pub mod texture;
+pub mod vertex;
 mod window;
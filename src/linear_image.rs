@@ -1,27 +1,29 @@
+use crate::allocator::{Allocation, MemoryAllocator, ResourceKind};
 use anyhow::Context;
 use ash::{
-    version::{DeviceV1_0, InstanceV1_0},
+    version::DeviceV1_0,
     vk::{
-        ComponentMapping, ComponentSwizzle, DeviceMemory, Extent3D, Format, Image,
-        ImageAspectFlags, ImageCreateInfo, ImageLayout, ImageSubresourceRange, ImageTiling,
+        ComponentMapping, ComponentSwizzle, Extent3D, Format, Image, ImageAspectFlags,
+        ImageCreateInfo, ImageLayout, ImageSubresource, ImageSubresourceRange, ImageTiling,
         ImageType, ImageUsageFlags, ImageView, ImageViewCreateInfo, ImageViewType,
-        MemoryAllocateInfo, MemoryPropertyFlags, PhysicalDevice, SampleCountFlags, SharingMode,
+        MemoryPropertyFlags, SampleCountFlags, SharingMode,
     },
-    Device, Instance,
+    Device,
 };
+use image::RgbaImage;
+use std::{path::Path, slice::from_raw_parts};
 
 pub struct ManagedAndLinearImage<'a> {
     device: &'a Device,
-    device_memory: DeviceMemory,
+    allocation: Allocation<'a>,
     image_raw: Image,
     image_view: ImageView,
 }
 
 impl<'a> ManagedAndLinearImage<'a> {
     pub fn new(
-        instance: &'a Instance,
-        physical_device: &PhysicalDevice,
         device: &'a Device,
+        allocator: &'a MemoryAllocator,
         width: u32,
         height: u32,
     ) -> anyhow::Result<ManagedAndLinearImage<'a>> {
@@ -43,34 +45,19 @@ impl<'a> ManagedAndLinearImage<'a> {
             .build();
         let image_raw = unsafe { device.create_image(&create_info, None) }
             .context("Failed to create linear image")?;
-        let memory_properties =
-            unsafe { instance.get_physical_device_memory_properties(*physical_device) };
-        let memory_requirements = unsafe { device.get_image_memory_requirements(image_raw) };
-        let memory_type_index = memory_properties
-            .memory_types
-            .iter()
-            .enumerate()
-            .find_map(|(index, memory_type)| {
-                let index = index as u32;
-                (memory_requirements.memory_type_bits & 2u32.pow(index) != 0
-                    && memory_type
-                        .property_flags
-                        .contains(MemoryPropertyFlags::HOST_VISIBLE))
-                .then(|| index)
-            })
-            .context("No suitable memory type")?;
-        let device_memory = unsafe {
-            device.allocate_memory(
-                &MemoryAllocateInfo::builder()
-                    .allocation_size(memory_requirements.size)
-                    .memory_type_index(memory_type_index)
-                    .build(),
-                None,
-            )
-        }
-        .context("Failed to allocate memory for linear image")?;
-        unsafe { device.bind_image_memory(image_raw, device_memory, 0) }
-            .context("Failed to bind device memory to linear image")?;
+        // CPU から読み出すので、フラッシュ不要なメモリを選ぶ (キャッシュされていれば読み出しが速い)
+        let allocation = match allocator.allocate_for_image(
+            image_raw,
+            ResourceKind::Linear,
+            MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+            MemoryPropertyFlags::HOST_CACHED,
+        ) {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                unsafe { device.destroy_image(image_raw, None) };
+                return Err(err).context("Failed to allocate memory for linear image");
+            }
+        };
         let image_view_create_info = ImageViewCreateInfo::builder()
             .image(image_raw)
             .view_type(ImageViewType::TYPE_2D)
@@ -97,11 +84,60 @@ impl<'a> ManagedAndLinearImage<'a> {
             .context("Failed to create ImageView for linear image")?;
         Ok(ManagedAndLinearImage {
             device,
-            device_memory,
+            allocation,
             image_raw,
             image_view,
         })
     }
+
+    pub fn get_image_raw(&self) -> Image {
+        self.image_raw
+    }
+
+    /// GPU メモリをマップして、行ピッチを考慮しながら RGBA 画像として読み出す
+    ///
+    /// 事前にレイアウトを `GENERAL` に遷移させ、書き込みの完了を待っておく必要がある
+    pub fn read_pixels(&self, width: u32, height: u32) -> anyhow::Result<RgbaImage> {
+        let subresource = ImageSubresource::builder()
+            .aspect_mask(ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .array_layer(0)
+            .build();
+        let layout = unsafe {
+            self.device
+                .get_image_subresource_layout(self.image_raw, subresource)
+        };
+        let mapped_memory =
+            self.allocation
+                .get_mapped_ptr()
+                .context("Memory of linear image is not mapped")? as *const u8;
+        let row_size = (width * 4) as usize;
+        let row_pitch = layout.row_pitch as usize;
+        let mut pixels = Vec::with_capacity(row_size * height as usize);
+        for row in 0..height as usize {
+            let row_data = unsafe {
+                from_raw_parts(
+                    mapped_memory.add(layout.offset as usize + row * row_pitch),
+                    row_size,
+                )
+            };
+            pixels.extend_from_slice(row_data);
+        }
+        RgbaImage::from_raw(width, height, pixels).context("Failed to create image::RgbaImage")
+    }
+
+    /// 画像ファイルとして保存する (形式は拡張子から判断される)
+    pub fn export<P>(&self, width: u32, height: u32, path: P) -> anyhow::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.read_pixels(width, height)?
+            .save(path)
+            .with_context(|| format!("Failed to save image to {}", path.display()))?;
+        debug!("Image was exported to {}", path.display());
+        Ok(())
+    }
 }
 
 impl Drop for ManagedAndLinearImage<'_> {
@@ -110,7 +146,5 @@ impl Drop for ManagedAndLinearImage<'_> {
         trace!("ImageView of linear image was destroyed");
         unsafe { self.device.destroy_image(self.image_raw, None) };
         trace!("Linear image was destroyed");
-        unsafe { self.device.free_memory(self.device_memory, None) };
-        trace!("GPU memory allocated for linear image was released");
     }
 }
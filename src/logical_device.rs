@@ -1,19 +1,60 @@
 use crate::{
-    command_pool::ManagedCommandPool, framebuffer::ManagedFramebuffer,
-    linear_image::ManagedAndLinearImage, optimized_image::ManagedAndOptimizedImage,
+    allocator::MemoryAllocator,
+    buffer::{ManagedBuffer, MemoryLocation},
+    command_pool::ManagedCommandPool,
+    descriptor::{
+        DescriptorBinding, DescriptorWriter, ManagedDescriptorPool, ManagedDescriptorSetLayout,
+    },
+    frame::FramesInFlight,
+    framebuffer::ManagedFramebuffer,
+    linear_image::ManagedAndLinearImage,
+    mesh::{self, Mesh, MeshData},
+    optimized_image::ManagedAndOptimizedImage,
+    pipeline_cache::ManagedPipelineCache,
     render_pass::ManagedRenderPass,
+    swapchain::ManagedSwapchain,
+    texture::{ManagedSampler, ManagedTexture, SamplerOptions},
+    window::ManagedWindow,
 };
+use anyhow::Context;
 use ash::{
-    version::DeviceV1_0,
-    vk::{PhysicalDevice, Queue},
+    version::{DeviceV1_0, InstanceV1_0},
+    vk::{BufferUsageFlags, Format, ImageLayout, PhysicalDevice, PhysicalDeviceFeatures, Queue},
     Device, Instance,
 };
+use image::RgbaImage;
+use std::path::Path;
+
+/// 論理デバイスの作成時に選んだキューファミリ
+#[derive(Clone, Copy, Debug)]
+pub struct QueueFamilyIndices {
+    pub graphics: u32,
+    /// ウィンドウ無しで論理デバイスを作った場合は `None`
+    pub presentation: Option<u32>,
+}
+
+impl QueueFamilyIndices {
+    /// 重複を除いたキューファミリのインデックス
+    pub fn unique(&self) -> Vec<u32> {
+        let mut indices = vec![self.graphics];
+        if let Some(presentation) = self.presentation {
+            if presentation != self.graphics {
+                indices.push(presentation);
+            }
+        }
+        indices
+    }
+}
 
 pub struct ManagedLogicalDevice<'a> {
     instance: &'a Instance,
     physical_device: PhysicalDevice,
     device_raw: Device,
-    queue_indices: Vec<u32>,
+    queue_indices: QueueFamilyIndices,
+    /// 論理デバイスの作成時に有効にした機能
+    enabled_features: PhysicalDeviceFeatures,
+    allocator: MemoryAllocator,
+    pipeline_cache: ManagedPipelineCache,
 }
 
 impl<'a> ManagedLogicalDevice<'a> {
@@ -21,28 +62,215 @@ impl<'a> ManagedLogicalDevice<'a> {
         instance: &'a Instance,
         physical_device: PhysicalDevice,
         device_raw: Device,
-        queue_indices: Vec<u32>,
+        queue_indices: QueueFamilyIndices,
+        enabled_features: PhysicalDeviceFeatures,
+        pipeline_cache: ManagedPipelineCache,
     ) -> ManagedLogicalDevice<'a> {
-        // 三角形を画像を描画するのが直近の目標なので、グラフィックスキューだけ利用して表示キューは放置
+        let allocator = MemoryAllocator::new(instance, &physical_device, device_raw.clone());
         ManagedLogicalDevice {
             instance,
             physical_device,
             device_raw,
             queue_indices,
+            enabled_features,
+            allocator,
+            pipeline_cache,
         }
     }
 
     pub fn get_graphics_queue(&self) -> Queue {
-        let graphics_queue_family_index = self.queue_indices[0];
         unsafe {
             self.device_raw
-                .get_device_queue(graphics_queue_family_index, 0)
+                .get_device_queue(self.queue_indices.graphics, 0)
         }
     }
 
+    pub fn get_pipeline_cache(&self) -> &ManagedPipelineCache {
+        &self.pipeline_cache
+    }
+
+    /// 論理デバイスの作成時に有効にした機能
+    pub fn get_enabled_features(&self) -> &PhysicalDeviceFeatures {
+        &self.enabled_features
+    }
+
+    pub fn get_presentation_queue(&self) -> Option<Queue> {
+        self.queue_indices
+            .presentation
+            .map(|index| unsafe { self.device_raw.get_device_queue(index, 0) })
+    }
+
     pub fn create_command_pool(&self) -> anyhow::Result<ManagedCommandPool> {
-        let graphics_queue_family_index = self.queue_indices[0];
-        ManagedCommandPool::new(&self.device_raw, graphics_queue_family_index)
+        ManagedCommandPool::new(&self.device_raw, self.queue_indices.graphics)
+    }
+
+    /// `frame_count` 個のフレームを並行して描画する描画ループを作成する
+    pub fn create_frames_in_flight(
+        &'a self,
+        command_pool: &'a ManagedCommandPool,
+        frame_count: usize,
+    ) -> anyhow::Result<FramesInFlight<'a>> {
+        FramesInFlight::new(
+            &self.device_raw,
+            command_pool,
+            self.get_graphics_queue(),
+            frame_count,
+        )
+    }
+
+    /// デバイスのすべてのキューの処理が終わるまで待つ
+    pub fn wait_idle(&self) -> anyhow::Result<()> {
+        unsafe { self.device_raw.device_wait_idle() }.context("Failed to wait for device idle")
+    }
+
+    pub fn create_buffer(
+        &self,
+        size: u64,
+        usage: BufferUsageFlags,
+        location: MemoryLocation,
+    ) -> anyhow::Result<ManagedBuffer> {
+        ManagedBuffer::new(&self.device_raw, &self.allocator, size, usage, location)
+    }
+
+    /// ステージングバッファを経由して、`data` を GPU 専用のメモリに置いたバッファへ転送する
+    ///
+    /// 転送はグラフィックスキューで行い、完了するまで待つ
+    pub fn create_device_local_buffer<T>(
+        &self,
+        command_pool: &ManagedCommandPool,
+        usage: BufferUsageFlags,
+        data: &[T],
+    ) -> anyhow::Result<ManagedBuffer>
+    where
+        T: Copy,
+    {
+        let size = std::mem::size_of_val(data) as u64;
+        let staging_buffer = self.create_buffer(
+            size,
+            BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        )?;
+        staging_buffer.write(data)?;
+        let buffer = self.create_buffer(
+            size,
+            usage | BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+        )?;
+        command_pool.allocate_command_buffer()?.copy_buffer(
+            &self.get_graphics_queue(),
+            &staging_buffer,
+            &buffer,
+            size,
+        )?;
+        Ok(buffer)
+    }
+
+    /// 読み込んだメッシュを GPU に転送する
+    pub fn create_mesh(
+        &self,
+        command_pool: &ManagedCommandPool,
+        meshes: &[MeshData],
+    ) -> anyhow::Result<Mesh> {
+        let (vertices, indices, submeshes) = mesh::merge(meshes);
+        ensure!(
+            !vertices.is_empty() && !indices.is_empty(),
+            "Mesh has no vertices or indices"
+        );
+        let vertex_buffer = self
+            .create_device_local_buffer(command_pool, BufferUsageFlags::VERTEX_BUFFER, &vertices)
+            .context("Failed to upload vertex buffer of mesh")?;
+        let index_buffer = self
+            .create_device_local_buffer(command_pool, BufferUsageFlags::INDEX_BUFFER, &indices)
+            .context("Failed to upload index buffer of mesh")?;
+        Ok(Mesh::new(vertex_buffer, index_buffer, submeshes))
+    }
+
+    pub fn create_descriptor_set_layout(
+        &self,
+        bindings: &[DescriptorBinding],
+    ) -> anyhow::Result<ManagedDescriptorSetLayout> {
+        ManagedDescriptorSetLayout::new(&self.device_raw, bindings)
+    }
+
+    pub fn create_descriptor_pool(&self) -> ManagedDescriptorPool {
+        ManagedDescriptorPool::new(&self.device_raw)
+    }
+
+    /// まとめておいた書き込みをディスクリプタセットに反映する
+    ///
+    /// GPU が使用中のセットを書き換えないこと
+    pub fn update_descriptor_sets(&self, writer: &DescriptorWriter) {
+        writer.update(&self.device_raw);
+    }
+
+    /// 異方性フィルタリングの上限は、デバイスの制限に合わせて丸める
+    pub fn create_sampler(&self, options: SamplerOptions) -> anyhow::Result<ManagedSampler> {
+        let max_supported_anisotropy = if self.enabled_features.sampler_anisotropy != 0 {
+            let properties = unsafe {
+                self.instance
+                    .get_physical_device_properties(self.physical_device)
+            };
+            Some(properties.limits.max_sampler_anisotropy)
+        } else {
+            None
+        };
+        ManagedSampler::new(&self.device_raw, options, max_supported_anisotropy)
+    }
+
+    /// 画像をステージングバッファ経由で GPU 専用のメモリに転送し、テクスチャを作る
+    ///
+    /// 画像は sRGB として扱う
+    pub fn create_texture(
+        &self,
+        command_pool: &ManagedCommandPool,
+        image: &RgbaImage,
+        options: SamplerOptions,
+    ) -> anyhow::Result<ManagedTexture> {
+        let (width, height) = image.dimensions();
+        let pixels = image.as_raw();
+        let staging_buffer = self.create_buffer(
+            pixels.len() as u64,
+            BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        )?;
+        staging_buffer.write(pixels)?;
+        let sampler = self.create_sampler(options)?;
+        let texture = ManagedTexture::new(
+            &self.device_raw,
+            &self.allocator,
+            sampler,
+            width,
+            height,
+            Format::R8G8B8A8_SRGB,
+        )?;
+        command_pool
+            .allocate_command_buffer()?
+            .copy_buffer_to_image(
+                &self.get_graphics_queue(),
+                &staging_buffer,
+                texture.get_image_raw(),
+                width,
+                height,
+            )
+            .context("Failed to upload texture")?;
+        Ok(texture)
+    }
+
+    /// PNG や JPEG などの画像ファイルを読み込んでテクスチャを作る
+    pub fn load_texture<P>(
+        &self,
+        command_pool: &ManagedCommandPool,
+        path: P,
+        options: SamplerOptions,
+    ) -> anyhow::Result<ManagedTexture>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let image = image::open(path)
+            .with_context(|| format!("Failed to load texture {}", path.display()))?
+            .into_rgba8();
+        self.create_texture(command_pool, &image, options)
     }
 
     pub fn create_optimized_image(
@@ -50,13 +278,7 @@ impl<'a> ManagedLogicalDevice<'a> {
         width: u32,
         height: u32,
     ) -> anyhow::Result<ManagedAndOptimizedImage> {
-        ManagedAndOptimizedImage::new(
-            self.instance,
-            &self.physical_device,
-            &self.device_raw,
-            width,
-            height,
-        )
+        ManagedAndOptimizedImage::new(&self.device_raw, &self.allocator, width, height)
     }
 
     pub fn create_linear_image(
@@ -64,17 +286,32 @@ impl<'a> ManagedLogicalDevice<'a> {
         width: u32,
         height: u32,
     ) -> anyhow::Result<ManagedAndLinearImage> {
-        ManagedAndLinearImage::new(
-            self.instance,
-            &self.physical_device,
+        ManagedAndLinearImage::new(&self.device_raw, &self.allocator, width, height)
+    }
+
+    /// オフスクリーン描画用のレンダーパスを作成する
+    pub fn create_render_pass(&self) -> anyhow::Result<ManagedRenderPass> {
+        ManagedRenderPass::new(
             &self.device_raw,
-            width,
-            height,
+            self.pipeline_cache.get_pipeline_cache_raw(),
+            self.enabled_features,
+            Format::R8G8B8A8_UNORM,
+            ImageLayout::GENERAL,
         )
     }
 
-    pub fn create_render_pass(&self) -> anyhow::Result<ManagedRenderPass> {
-        ManagedRenderPass::new(&self.device_raw)
+    /// スワップチェーンのイメージに描画して表示するためのレンダーパスを作成する
+    pub fn create_swapchain_render_pass(
+        &self,
+        swapchain: &ManagedSwapchain,
+    ) -> anyhow::Result<ManagedRenderPass> {
+        ManagedRenderPass::new(
+            &self.device_raw,
+            self.pipeline_cache.get_pipeline_cache_raw(),
+            self.enabled_features,
+            swapchain.get_format(),
+            ImageLayout::PRESENT_SRC_KHR,
+        )
     }
 
     pub fn create_framebuffer(
@@ -87,15 +324,37 @@ impl<'a> ManagedLogicalDevice<'a> {
         ManagedFramebuffer::new(
             &self.device_raw,
             render_pass,
-            connectable_image,
+            connectable_image.get_attachment_view(),
             width,
             height,
         )
     }
+
+    /// 作り直す場合は、`old_swapchain` に今のスワップチェーンを渡す
+    pub fn create_swapchain(
+        &'a self,
+        window: &'a ManagedWindow,
+        old_swapchain: Option<&ManagedSwapchain>,
+    ) -> anyhow::Result<ManagedSwapchain<'a>> {
+        let presentation_queue = self
+            .get_presentation_queue()
+            .context("Logical device was created without a presentation queue")?;
+        ManagedSwapchain::new(
+            self.instance,
+            &self.physical_device,
+            &self.device_raw,
+            window,
+            self.queue_indices,
+            presentation_queue,
+            old_swapchain,
+        )
+    }
 }
 
 impl Drop for ManagedLogicalDevice<'_> {
     fn drop(&mut self) {
+        self.pipeline_cache.destroy();
+        self.allocator.destroy();
         unsafe { self.device_raw.destroy_device(None) };
         trace!("Logical device was destroyed")
     }
@@ -1,35 +1,310 @@
 extern crate game;
 
-use ash::Entry;
-use game::{glfw_wrapper::GlfwWrapper, instance::ManagedInstance};
+use anyhow::Context;
+use ash::{
+    vk::{BufferUsageFlags, ShaderStageFlags},
+    Entry,
+};
+use game::{
+    config::{self, Config},
+    glfw_wrapper::GlfwWrapper,
+    headless, info,
+    input::Event,
+    instance::ManagedInstance,
+    physical_device::{DeviceSelection, DEVICE_ENV_VAR},
+    pipeline::{PipelineBuilder, ReloadablePipeline},
+    shader::{self, ShaderLibrary, DEFAULT_FRAGMENT_SHADER, DEFAULT_VERTEX_SHADER},
+    vertex::{ColorVertex2D, TRIANGLE_VERTICES},
+};
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "\
+Usage:
+    game [--width W] [--height H] [--device INDEX|NAME]
+    game render [--width W] [--height H] [--out PATH] [--device INDEX|NAME]
+    game devices
+    game info [--out PATH]
+
+Commands:
+    (none)    Open a window and render frames until it is closed
+    render    Render a frame without a window and save it as an image file
+    devices   List physical devices and whether each of them can be used
+    info      Dump Vulkan capabilities as JSON for bug reports (to stdout unless --out is given)
+
+The device can also be chosen with the GAME_DEVICE environment variable,
+or with `device = \"INDEX|NAME\"` in the config file (GAME_CONFIG overrides its path).
+Shaders are loaded from the GAME_SHADER_DIR directory (./shaders by default),
+and reloaded while the window is open when they are rebuilt.";
+
+/// CPU が先行して記録できるフレームの数
+const MAX_FRAMES_IN_FLIGHT: usize = 2;
+
+enum Command {
+    Run {
+        width: u32,
+        height: u32,
+        /// `--device` が無ければ `None`
+        device: Option<DeviceSelection>,
+    },
+    Render {
+        width: u32,
+        height: u32,
+        out: PathBuf,
+        /// `--device` が無ければ `None`
+        device: Option<DeviceSelection>,
+    },
+    Devices,
+    Info {
+        out: Option<PathBuf>,
+    },
+}
+
+fn parse_args<Args>(args: Args) -> anyhow::Result<Command>
+where
+    Args: Iterator<Item = String>,
+{
+    let mut args = args.peekable();
+    if args.peek().map(String::as_str) == Some("devices") {
+        args.next();
+        if let Some(arg) = args.next() {
+            anyhow::bail!("Unknown argument: {}\n\n{}", arg, USAGE);
+        }
+        return Ok(Command::Devices);
+    }
+    if args.peek().map(String::as_str) == Some("info") {
+        args.next();
+        let mut out = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--out" => out = Some(args.next().context("--out requires a value")?.into()),
+                _ => anyhow::bail!("Unknown argument: {}\n\n{}", arg, USAGE),
+            }
+        }
+        return Ok(Command::Info { out });
+    }
+    let headless = args.peek().map(String::as_str) == Some("render");
+    if headless {
+        args.next();
+    }
+    let mut device = None;
+    let mut width: u32 = 500;
+    let mut height: u32 = 300;
+    let mut out = PathBuf::from("triangle.png");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => {
+                width = args
+                    .next()
+                    .context("--width requires a value")?
+                    .parse()
+                    .context("Failed to parse --width")?
+            }
+            "--height" => {
+                height = args
+                    .next()
+                    .context("--height requires a value")?
+                    .parse()
+                    .context("Failed to parse --height")?
+            }
+            "--device" => {
+                device = Some(DeviceSelection::parse(
+                    &args.next().context("--device requires a value")?,
+                ))
+            }
+            "--out" if headless => out = args.next().context("--out requires a value")?.into(),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => anyhow::bail!("Unknown argument: {}\n\n{}", arg, USAGE),
+        }
+    }
+    if headless {
+        Ok(Command::Render {
+            width,
+            height,
+            out,
+            device,
+        })
+    } else {
+        Ok(Command::Run {
+            width,
+            height,
+            device,
+        })
+    }
+}
 
 fn main() -> anyhow::Result<()> {
     env_logger::init();
-    let width: u32 = 500;
-    let height: u32 = 300;
+    match parse_args(std::env::args().skip(1))? {
+        Command::Run {
+            width,
+            height,
+            device,
+        } => run(width, height, resolve_device_selection(device)?),
+        Command::Render {
+            width,
+            height,
+            out,
+            device,
+        } => render(width, height, &out, resolve_device_selection(device)?),
+        Command::Devices => devices(),
+        Command::Info { out } => info(out.as_deref()),
+    }
+}
+
+/// コマンドライン引数、環境変数、設定ファイルの順に優先する
+fn resolve_device_selection(device: Option<DeviceSelection>) -> anyhow::Result<DeviceSelection> {
+    if let Some(device) = device {
+        return Ok(device);
+    }
+    let config = match config::default_path() {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    Ok(DeviceSelection::from_env_or(config.device_selection()))
+}
+
+fn run(width: u32, height: u32, device: DeviceSelection) -> anyhow::Result<()> {
     let entry = unsafe { Entry::new() }?;
     let glfw = GlfwWrapper::new()?;
-    let instance = ManagedInstance::new(&entry, &glfw, cfg!(feature = "validation_layers"))?;
-    // let window = instance.create_window(width, height, "Game")?;
-    // let logical_device = instance.create_logical_device(Some(&window))?;
-    let logical_device = instance.create_logical_device(None)?;
+    let mut instance =
+        ManagedInstance::new(&entry, Some(&glfw), cfg!(feature = "validation_layers"))?;
+    instance.set_device_selection(device);
+    let window = instance.create_window(width, height, "Game")?;
+    let logical_device = instance.create_logical_device(Some(&window))?;
     let command_pool = logical_device.create_command_pool()?;
-    let graphics_queue = logical_device.get_graphics_queue();
-    let command_buffer = command_pool.allocate_command_buffer()?;
-    let optimized_image = logical_device.create_optimized_image(width, height)?;
-    let _linear_image = logical_device.create_linear_image(width, height)?;
-    let render_pass = logical_device.create_render_pass()?;
-    let pipeline = render_pass.create_graphics_pipeline(width, height)?;
-    let framebuffer =
-        logical_device.create_framebuffer(&render_pass, &optimized_image, width, height)?;
-    command_buffer.draw_triangle(
-        &graphics_queue,
-        &render_pass,
-        &framebuffer,
-        &pipeline,
-        width,
-        height,
+    let vertex_buffer = logical_device.create_device_local_buffer(
+        &command_pool,
+        BufferUsageFlags::VERTEX_BUFFER,
+        &TRIANGLE_VERTICES,
     )?;
-    // image.export_bitmap(width, height)?;
+    let mut frames = logical_device.create_frames_in_flight(&command_pool, MAX_FRAMES_IN_FLIGHT)?;
+    let mut library = ShaderLibrary::new(shader::default_dir());
+    if let Err(err) = library.watch() {
+        // 監視できなくても、起動時に読み込んだシェーダで描画は続けられる
+        log::warn!("Shader hot reload is disabled: {:?}", err);
+    }
+    // 作り直すときに、表示中のイメージを新しいスワップチェーンに引き継ぐ
+    let mut old_swapchain = None;
+    // スワップチェーンとそのサイズに依存するオブジェクトは、作り直しが必要になるたびにこのループで作り直す
+    while !window.should_close() {
+        let (framebuffer_width, framebuffer_height) = window.get_framebuffer_size();
+        if framebuffer_width == 0 || framebuffer_height == 0 {
+            // 最小化されている間は描画を止める
+            glfw.wait_events();
+            // 元に戻ったときにはスワップチェーンを作り直すので、溜まったイベントは読み捨てる
+            window.flush_events().for_each(drop);
+            continue;
+        }
+        let swapchain = logical_device.create_swapchain(&window, old_swapchain.as_ref())?;
+        // 古いスワップチェーンは使い終わるまで待ってから手放しているので、ここで破棄してよい
+        if old_swapchain.take().is_some() {
+            log::debug!("Swapchain was released");
+        }
+        let extent = swapchain.get_extent();
+        let render_pass = logical_device.create_swapchain_render_pass(&swapchain)?;
+        let mut pipeline = ReloadablePipeline::new(
+            &library,
+            PipelineBuilder::new().vertex_input::<ColorVertex2D>(),
+            &[
+                (ShaderStageFlags::VERTEX, DEFAULT_VERTEX_SHADER),
+                (ShaderStageFlags::FRAGMENT, DEFAULT_FRAGMENT_SHADER),
+            ],
+            &render_pass,
+        )?;
+        let framebuffers = swapchain.create_framebuffers(&render_pass)?;
+        frames.reset_images_in_flight();
+        while !window.should_close() {
+            glfw.poll_events();
+            let resized = window
+                .flush_events()
+                .filter(|event| matches!(event, Event::Resized { .. }))
+                .count()
+                > 0;
+            if resized {
+                break;
+            }
+            let changed = library.poll_changes();
+            pipeline.reload_if_changed(&library, &render_pass, &changed);
+            let needs_recreation =
+                frames.draw_frame(&swapchain, |command_buffer, image_index| {
+                    command_buffer.record_draw(
+                        &render_pass,
+                        &framebuffers[image_index],
+                        pipeline.get_pipeline(),
+                        &vertex_buffer,
+                        TRIANGLE_VERTICES.len() as u32,
+                        extent.width,
+                        extent.height,
+                    )
+                })?;
+            if needs_recreation {
+                break;
+            }
+        }
+        // 使用中のスワップチェーンを破棄しないように待つ
+        logical_device.wait_idle()?;
+        drop(framebuffers);
+        old_swapchain = Some(swapchain);
+    }
+    Ok(())
+}
+
+/// GLFW を初期化せずに描画するので、ディスプレイの無いビルドサーバ上でも動く
+fn render(width: u32, height: u32, out: &Path, device: DeviceSelection) -> anyhow::Result<()> {
+    let entry = unsafe { Entry::new() }?;
+    let mut instance = ManagedInstance::new(&entry, None, cfg!(feature = "validation_layers"))?;
+    instance.set_device_selection(device);
+    let image = headless::render_triangle(&instance, width, height)?;
+    image
+        .save(out)
+        .with_context(|| format!("Failed to save image to {}", out.display()))?;
+    log::info!("Rendered {}x{} frame to {}", width, height, out.display());
+    Ok(())
+}
+
+/// ウィンドウへの表示に対応しているかどうかは、ウィンドウを作るまで分からないので判定しない
+fn devices() -> anyhow::Result<()> {
+    let entry = unsafe { Entry::new() }?;
+    let instance = ManagedInstance::new(&entry, None, false)?;
+    let candidates = instance.list_physical_devices(None)?;
+    if candidates.is_empty() {
+        println!("No physical devices found");
+    }
+    for candidate in &candidates {
+        println!(
+            "#{} {} ({:?}, {} MiB device local memory)",
+            candidate.index,
+            candidate.name,
+            candidate.device_type,
+            candidate.score.device_local_memory / (1024 * 1024)
+        );
+        match &candidate.rejection {
+            None => println!("    accepted: {:?}", candidate.score),
+            Some(rejection) => println!("    rejected: {}", rejection),
+        }
+    }
+    println!(
+        "\nSet {}=<index or name> or pass --device to choose one explicitly.",
+        DEVICE_ENV_VAR
+    );
+    if let Some(path) = config::default_path() {
+        println!("It can also be set as `device` in {}.", path.display());
+    }
+    Ok(())
+}
+
+fn info(out: Option<&Path>) -> anyhow::Result<()> {
+    let entry = unsafe { Entry::new() }?;
+    let instance = ManagedInstance::new(&entry, None, false)?;
+    let info = info::collect(&instance)?;
+    let json = serde_json::to_string_pretty(&info).context("Failed to serialize info")?;
+    match out {
+        Some(out) => std::fs::write(out, json)
+            .with_context(|| format!("Failed to write info to {}", out.display()))?,
+        None => println!("{}", json),
+    }
     Ok(())
 }
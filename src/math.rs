@@ -0,0 +1,216 @@
+//! ベクトル・行列・クォータニオンと、カメラ
+//!
+//! ワールド座標は右手系で、3D では Y が上を向く。
+//! 射影行列は Vulkan のクリップ空間 (Y が下向き、深度が 0..1) に合わせて作る
+
+pub use glam::{EulerRot, Mat3, Mat4, Quat, Vec2, Vec3, Vec4};
+
+/// 平行移動・回転・拡大縮小
+///
+/// 行列にするときは、拡大縮小 → 回転 → 平行移動 の順に適用する
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn from_translation(translation: Vec3) -> Transform {
+        Transform {
+            translation,
+            ..Transform::IDENTITY
+        }
+    }
+
+    pub fn from_rotation(rotation: Quat) -> Transform {
+        Transform {
+            rotation,
+            ..Transform::IDENTITY
+        }
+    }
+
+    pub fn from_scale(scale: Vec3) -> Transform {
+        Transform {
+            scale,
+            ..Transform::IDENTITY
+        }
+    }
+
+    /// 拡大縮小を含む行列から作る (せん断は失われる)
+    pub fn from_matrix(matrix: Mat4) -> Transform {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn to_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    /// `target` の方を向くように回転を設定する
+    ///
+    /// 向きが `up` と平行なときは別の軸を上とみなす。`target` が現在位置と同じなら何もしない
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let direction = target - self.translation;
+        if direction.length_squared() < f32::EPSILON {
+            return;
+        }
+        let forward = direction.normalize();
+        let right = match [up, Vec3::Z, Vec3::X]
+            .iter()
+            .map(|up| forward.cross(*up))
+            .find(|right| right.length_squared() > 1e-6)
+        {
+            Some(right) => right.normalize(),
+            None => return,
+        };
+        let up = right.cross(forward);
+        // 前方は -Z
+        self.rotation = Quat::from_mat3(&Mat3::from_cols(right, up, -forward));
+    }
+
+    /// 前方 (-Z) の向き
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * -Vec3::Z
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.rotation * Vec3::X
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.rotation * Vec3::Y
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.translation + self.rotation * (self.scale * point)
+    }
+
+    /// `self` を親、`child` を子として合成する
+    pub fn mul_transform(&self, child: &Transform) -> Transform {
+        Transform {
+            translation: self.transform_point(child.translation),
+            rotation: self.rotation * child.rotation,
+            scale: self.scale * child.scale,
+        }
+    }
+}
+
+/// 2D 用の正射影カメラ
+///
+/// ワールド座標はピクセルと同じく Y が下向きで、`zoom` が 1 のときワールドの 1 単位が 1 ピクセルになる
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera2D {
+    /// 画面の中央に映るワールド座標
+    pub position: Vec2,
+    /// 時計回りの回転 (ラジアン)
+    pub rotation: f32,
+    pub zoom: f32,
+    /// 描画先の大きさ (ピクセル)
+    pub viewport_size: Vec2,
+}
+
+impl Camera2D {
+    /// 左上がワールドの原点に来るカメラ
+    pub fn new(width: u32, height: u32) -> Camera2D {
+        let viewport_size = Vec2::new(width as f32, height as f32);
+        Camera2D {
+            position: viewport_size / 2.0,
+            rotation: 0.0,
+            zoom: 1.0,
+            viewport_size,
+        }
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::from_rotation_z(-self.rotation)
+            * Mat4::from_translation(Vec3::new(-self.position.x, -self.position.y, 0.0))
+    }
+
+    /// 深度は 0..1 のまま通す
+    pub fn projection(&self) -> Mat4 {
+        let scale = 2.0 * self.zoom / self.viewport_size;
+        Mat4::from_scale(Vec3::new(scale.x, scale.y, 1.0))
+    }
+
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection() * self.view()
+    }
+
+    /// 画面上の位置 (左上が原点のピクセル座標) をワールド座標に変換する
+    pub fn screen_to_world(&self, screen: Vec2) -> Vec2 {
+        let ndc = screen / self.viewport_size * 2.0 - Vec2::ONE;
+        let world = self
+            .view_projection()
+            .inverse()
+            .transform_point3(ndc.extend(0.0));
+        Vec2::new(world.x, world.y)
+    }
+}
+
+/// 3D 用の透視投影カメラ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera3D {
+    /// カメラの位置と向き (前方は -Z)
+    pub transform: Transform,
+    /// 縦の視野角 (ラジアン)
+    pub fov_y: f32,
+    /// 幅 / 高さ
+    pub aspect_ratio: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera3D {
+    pub fn new(fov_y: f32, width: u32, height: u32) -> Camera3D {
+        Camera3D {
+            transform: Transform::IDENTITY,
+            fov_y,
+            aspect_ratio: width as f32 / height as f32,
+            near: 0.1,
+            far: 1000.0,
+        }
+    }
+
+    /// 描画先の大きさが変わったときに呼ぶ
+    pub fn set_viewport_size(&mut self, width: u32, height: u32) {
+        self.aspect_ratio = width as f32 / height as f32;
+    }
+
+    pub fn look_at(&mut self, target: Vec3) {
+        self.transform.look_at(target, Vec3::Y);
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::from_rotation_translation(self.transform.rotation, self.transform.translation)
+            .inverse()
+    }
+
+    /// Y を反転させるので、反時計回りのポリゴンはそのまま反時計回りとして表になる
+    pub fn projection(&self) -> Mat4 {
+        let mut projection =
+            Mat4::perspective_rh(self.fov_y, self.aspect_ratio, self.near, self.far);
+        projection.y_axis.y = -projection.y_axis.y;
+        projection
+    }
+
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection() * self.view()
+    }
+}
@@ -0,0 +1,261 @@
+//! メッシュの読み込み (glTF 2.0 と Wavefront OBJ)
+
+use crate::{buffer::ManagedBuffer, impl_vertex};
+use anyhow::Context;
+use std::path::Path;
+
+/// メッシュの頂点 (ファイルに含まれていない属性は既定値で埋める)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// 左上が原点
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl_vertex!(MeshVertex {
+    position,
+    normal,
+    uv,
+    color,
+});
+
+impl Default for MeshVertex {
+    fn default() -> Self {
+        MeshVertex {
+            position: [0.0; 3],
+            normal: [0.0, 0.0, 1.0],
+            uv: [0.0; 2],
+            color: [1.0; 4],
+        }
+    }
+}
+
+/// CPU 側に読み込んだ、1 プリミティブ分のメッシュ
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshData {
+    pub name: String,
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+/// 拡張子 (`.gltf` , `.glb` , `.obj`) から形式を判断して読み込む
+pub fn load<P>(path: P) -> anyhow::Result<Vec<MeshData>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("gltf") | Some("glb") => load_gltf(path),
+        Some("obj") => load_obj(path),
+        _ => bail!("Unsupported mesh format: {}", path.display()),
+    }
+}
+
+/// glTF のすべてのメッシュのすべてのプリミティブを読み込む
+///
+/// ノードの変換は適用しないので、頂点はメッシュのローカル座標のまま
+pub fn load_gltf<P>(path: P) -> anyhow::Result<Vec<MeshData>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let (document, buffers, _) = gltf::import(path)
+        .with_context(|| format!("Failed to load glTF file {}", path.display()))?;
+    let mut meshes = Vec::new();
+    for mesh in document.meshes() {
+        let mesh_name = mesh
+            .name()
+            .map_or_else(|| format!("mesh{}", mesh.index()), str::to_owned);
+        for primitive in mesh.primitives() {
+            let name = format!("{}#{}", mesh_name, primitive.index());
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                warn!(
+                    "Skipped glTF primitive {} in {} ({:?} is not supported)",
+                    name,
+                    path.display(),
+                    primitive.mode()
+                );
+                continue;
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let mut vertices: Vec<MeshVertex> = reader
+                .read_positions()
+                .with_context(|| {
+                    format!(
+                        "glTF primitive {} in {} has no positions",
+                        name,
+                        path.display()
+                    )
+                })?
+                .map(|position| MeshVertex {
+                    position,
+                    ..MeshVertex::default()
+                })
+                .collect();
+            if let Some(normals) = reader.read_normals() {
+                for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                    vertex.normal = normal;
+                }
+            }
+            if let Some(uvs) = reader.read_tex_coords(0) {
+                for (vertex, uv) in vertices.iter_mut().zip(uvs.into_f32()) {
+                    vertex.uv = uv;
+                }
+            }
+            if let Some(colors) = reader.read_colors(0) {
+                for (vertex, color) in vertices.iter_mut().zip(colors.into_rgba_f32()) {
+                    vertex.color = color;
+                }
+            }
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+            meshes.push(MeshData {
+                name,
+                vertices,
+                indices,
+            });
+        }
+    }
+    ensure!(
+        !meshes.is_empty(),
+        "glTF file {} contains no triangle meshes",
+        path.display()
+    );
+    Ok(meshes)
+}
+
+/// OBJ のすべてのオブジェクトを読み込む (多角形は三角形に分割する)
+///
+/// マテリアルは読み込まない
+pub fn load_obj<P>(path: P) -> anyhow::Result<Vec<MeshData>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ignore_points: true,
+        ignore_lines: true,
+    };
+    let (models, _) = tobj::load_obj(path, &options)
+        .with_context(|| format!("Failed to load OBJ file {}", path.display()))?;
+    ensure!(
+        !models.is_empty(),
+        "OBJ file {} contains no objects",
+        path.display()
+    );
+    Ok(models
+        .into_iter()
+        .map(|model| {
+            let mesh = model.mesh;
+            let vertices = (0..mesh.positions.len() / 3)
+                .map(|index| {
+                    let mut vertex = MeshVertex {
+                        position: read_vec3(&mesh.positions, index),
+                        ..MeshVertex::default()
+                    };
+                    if !mesh.normals.is_empty() {
+                        vertex.normal = read_vec3(&mesh.normals, index);
+                    }
+                    // OBJ のテクスチャ座標は左下が原点
+                    if !mesh.texcoords.is_empty() {
+                        vertex.uv = [
+                            mesh.texcoords[index * 2],
+                            1.0 - mesh.texcoords[index * 2 + 1],
+                        ];
+                    }
+                    if !mesh.vertex_color.is_empty() {
+                        let [r, g, b] = read_vec3(&mesh.vertex_color, index);
+                        vertex.color = [r, g, b, 1.0];
+                    }
+                    vertex
+                })
+                .collect();
+            MeshData {
+                name: model.name,
+                vertices,
+                indices: mesh.indices,
+            }
+        })
+        .collect())
+}
+
+fn read_vec3(values: &[f32], index: usize) -> [f32; 3] {
+    [
+        values[index * 3],
+        values[index * 3 + 1],
+        values[index * 3 + 2],
+    ]
+}
+
+/// `Mesh` の中の 1 プリミティブ分の範囲
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Submesh {
+    pub name: String,
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,
+}
+
+/// GPU に転送済みのメッシュ
+///
+/// すべてのプリミティブを 1 つの頂点バッファとインデックスバッファにまとめて持つ
+pub struct Mesh<'a> {
+    vertex_buffer: ManagedBuffer<'a>,
+    index_buffer: ManagedBuffer<'a>,
+    submeshes: Vec<Submesh>,
+}
+
+impl<'a> Mesh<'a> {
+    pub fn new(
+        vertex_buffer: ManagedBuffer<'a>,
+        index_buffer: ManagedBuffer<'a>,
+        submeshes: Vec<Submesh>,
+    ) -> Mesh<'a> {
+        Mesh {
+            vertex_buffer,
+            index_buffer,
+            submeshes,
+        }
+    }
+
+    pub fn get_vertex_buffer(&self) -> &ManagedBuffer<'a> {
+        &self.vertex_buffer
+    }
+
+    pub fn get_index_buffer(&self) -> &ManagedBuffer<'a> {
+        &self.index_buffer
+    }
+
+    pub fn get_submeshes(&self) -> &[Submesh] {
+        &self.submeshes
+    }
+}
+
+/// 複数のプリミティブを 1 つの頂点配列とインデックス配列にまとめる
+pub fn merge(meshes: &[MeshData]) -> (Vec<MeshVertex>, Vec<u32>, Vec<Submesh>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut submeshes = Vec::with_capacity(meshes.len());
+    for mesh in meshes {
+        submeshes.push(Submesh {
+            name: mesh.name.clone(),
+            first_index: indices.len() as u32,
+            index_count: mesh.indices.len() as u32,
+            vertex_offset: vertices.len() as i32,
+        });
+        vertices.extend_from_slice(&mesh.vertices);
+        indices.extend_from_slice(&mesh.indices);
+    }
+    (vertices, indices, submeshes)
+}
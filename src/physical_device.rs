@@ -0,0 +1,349 @@
+//! 物理デバイスの列挙と選択
+
+use crate::{logical_device::QueueFamilyIndices, window::ManagedWindow};
+use anyhow::Context;
+use ash::{
+    extensions::khr::Swapchain,
+    version::InstanceV1_0,
+    vk::{
+        Bool32, MemoryHeapFlags, PhysicalDevice, PhysicalDeviceFeatures, PhysicalDeviceType,
+        QueueFamilyProperties, QueueFlags, TRUE,
+    },
+    Instance,
+};
+use std::{cmp::Ordering, ffi::CStr};
+
+/// 使うデバイスを指定する環境変数 (インデックスまたは名前の一部)
+pub const DEVICE_ENV_VAR: &str = "GAME_DEVICE";
+
+/// `PhysicalDeviceFeatures` のうち、1 つの機能のフィールド
+type FeatureField = fn(&mut PhysicalDeviceFeatures) -> &mut Bool32;
+
+/// 無いと描画できないので、対応していないデバイスを除外する機能
+const REQUIRED_FEATURES: &[(&str, FeatureField)] = &[
+    // メッシュのインデックスは u32 なので、2^24 以上の値も使えなければならない
+    ("fullDrawIndexUint32", |features| {
+        &mut features.full_draw_index_uint32
+    }),
+];
+
+/// あると嬉しいが、無くても動く機能 (対応していれば有効にする)
+const OPTIONAL_FEATURES: &[(&str, FeatureField)] = &[
+    ("samplerAnisotropy", |features| {
+        &mut features.sampler_anisotropy
+    }),
+    ("fillModeNonSolid", |features| {
+        &mut features.fill_mode_non_solid
+    }),
+    ("wideLines", |features| &mut features.wide_lines),
+];
+
+fn is_supported(supported: &PhysicalDeviceFeatures, field: FeatureField) -> bool {
+    let mut features = *supported;
+    *field(&mut features) != 0
+}
+
+/// 論理デバイスで有効にする機能 (必須の機能と、対応している任意の機能)
+pub(crate) fn features_to_enable(supported: &PhysicalDeviceFeatures) -> PhysicalDeviceFeatures {
+    let mut enabled = PhysicalDeviceFeatures::default();
+    let optional = OPTIONAL_FEATURES
+        .iter()
+        .filter(|(_, field)| is_supported(supported, *field));
+    for (_, field) in REQUIRED_FEATURES.iter().chain(optional) {
+        *field(&mut enabled) = TRUE;
+    }
+    enabled
+}
+
+/// どの物理デバイスを使うか
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelection {
+    /// 最もスコアの高いデバイスを使う
+    Auto,
+    /// `vkEnumeratePhysicalDevices` の順番で指定する
+    Index(usize),
+    /// 名前の一部で指定する (大文字と小文字は区別しない)
+    Name(String),
+}
+
+impl DeviceSelection {
+    /// 数値ならインデックス、それ以外なら名前として解釈する
+    pub fn parse(value: &str) -> DeviceSelection {
+        let value = value.trim();
+        if value.is_empty() {
+            DeviceSelection::Auto
+        } else if let Ok(index) = value.parse() {
+            DeviceSelection::Index(index)
+        } else {
+            DeviceSelection::Name(value.to_owned())
+        }
+    }
+
+    /// 環境変数 `GAME_DEVICE` から読み込む (設定されていなければ `Auto`)
+    pub fn from_env() -> DeviceSelection {
+        DeviceSelection::from_env_or(DeviceSelection::Auto)
+    }
+
+    /// 環境変数 `GAME_DEVICE` が設定されていなければ `fallback` を返す
+    pub fn from_env_or(fallback: DeviceSelection) -> DeviceSelection {
+        std::env::var(DEVICE_ENV_VAR)
+            .map(|value| DeviceSelection::parse(&value))
+            .unwrap_or(fallback)
+    }
+}
+
+/// デバイスの優先度 (フィールドの順に比較する)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DeviceScore {
+    /// ディスクリート > 統合 > 仮想 > CPU > その他
+    pub type_rank: u32,
+    /// デバイスローカルなメモリの合計 (バイト)
+    pub device_local_memory: u64,
+    /// 対応している任意機能の数
+    pub optional_features: u32,
+}
+
+impl DeviceScore {
+    pub fn type_rank(device_type: PhysicalDeviceType) -> u32 {
+        match device_type {
+            PhysicalDeviceType::DISCRETE_GPU => 4,
+            PhysicalDeviceType::INTEGRATED_GPU => 3,
+            PhysicalDeviceType::VIRTUAL_GPU => 2,
+            PhysicalDeviceType::CPU => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// 物理デバイスの候補と、それが使えるかどうかの判定結果
+pub struct DeviceCandidate {
+    pub index: usize,
+    pub name: String,
+    pub device_type: PhysicalDeviceType,
+    pub score: DeviceScore,
+    /// 使えないデバイスなら、その理由
+    pub rejection: Option<String>,
+    pub(crate) physical_device: PhysicalDevice,
+    pub(crate) queue_indices: Option<QueueFamilyIndices>,
+}
+
+impl DeviceCandidate {
+    pub fn is_suitable(&self) -> bool {
+        self.rejection.is_none()
+    }
+}
+
+/// すべての物理デバイスを列挙して採点する
+///
+/// `window` を渡した場合は、そのウィンドウに表示できないデバイスを除外する
+pub(crate) fn enumerate_candidates(
+    instance_raw: &Instance,
+    window: Option<&ManagedWindow>,
+) -> anyhow::Result<Vec<DeviceCandidate>> {
+    let physical_devices = unsafe { instance_raw.enumerate_physical_devices() }
+        .context("Failed to enumerate physical devices")?;
+    Ok(physical_devices
+        .into_iter()
+        .enumerate()
+        .map(|(index, physical_device)| {
+            evaluate_candidate(instance_raw, index, physical_device, window)
+        })
+        .collect())
+}
+
+/// `selection` に従って使うデバイスを決める
+pub(crate) fn select_candidate<'c>(
+    candidates: &'c [DeviceCandidate],
+    selection: &DeviceSelection,
+) -> anyhow::Result<&'c DeviceCandidate> {
+    let selected = match selection {
+        DeviceSelection::Auto => {
+            return candidates
+                .iter()
+                .filter(|candidate| candidate.is_suitable())
+                .max_by(|a, b| compare_candidates(a, b))
+                .context("No suitable physical device")
+        }
+        DeviceSelection::Index(index) => candidates
+            .iter()
+            .find(|candidate| candidate.index == *index)
+            .with_context(|| {
+                format!(
+                    "Physical device #{} does not exist ({} found)",
+                    index,
+                    candidates.len()
+                )
+            })?,
+        DeviceSelection::Name(name) => {
+            let pattern = name.to_lowercase();
+            let matches: Vec<&DeviceCandidate> = candidates
+                .iter()
+                .filter(|candidate| candidate.name.to_lowercase().contains(&pattern))
+                .collect();
+            // 同じ名前のデバイスが複数ある場合は、使えるものの中から自動選択と同じ基準で選ぶ
+            if let Some(selected) = matches
+                .iter()
+                .copied()
+                .filter(|candidate| candidate.is_suitable())
+                .max_by(|a, b| compare_candidates(a, b))
+            {
+                return Ok(selected);
+            }
+            match matches.as_slice() {
+                [] => bail!("No physical device matches {:?}", name),
+                [selected] => *selected,
+                _ => bail!(
+                    "None of the physical devices matching {:?} can be used:\n{}",
+                    name,
+                    matches
+                        .iter()
+                        .map(|candidate| format!(
+                            "    #{} ({}): {}",
+                            candidate.index,
+                            candidate.name,
+                            candidate.rejection.as_deref().unwrap_or_default()
+                        ))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                ),
+            }
+        }
+    };
+    if let Some(rejection) = &selected.rejection {
+        bail!(
+            "Physical device #{} ({}) cannot be used: {}",
+            selected.index,
+            selected.name,
+            rejection
+        );
+    }
+    Ok(selected)
+}
+
+/// スコアが同じなら、先に列挙されたデバイスを優先する
+fn compare_candidates(a: &DeviceCandidate, b: &DeviceCandidate) -> Ordering {
+    a.score.cmp(&b.score).then(b.index.cmp(&a.index))
+}
+
+fn evaluate_candidate(
+    instance_raw: &Instance,
+    index: usize,
+    physical_device: PhysicalDevice,
+    window: Option<&ManagedWindow>,
+) -> DeviceCandidate {
+    let properties = unsafe { instance_raw.get_physical_device_properties(physical_device) };
+    let features = unsafe { instance_raw.get_physical_device_features(physical_device) };
+    let memory_properties =
+        unsafe { instance_raw.get_physical_device_memory_properties(physical_device) };
+    let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+        .to_string_lossy()
+        .into_owned();
+    let device_local_memory = memory_properties.memory_heaps
+        [..memory_properties.memory_heap_count as usize]
+        .iter()
+        .filter(|heap| heap.flags.contains(MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .sum();
+    let optional_features = OPTIONAL_FEATURES
+        .iter()
+        .filter(|(_, field)| is_supported(&features, *field))
+        .count() as u32;
+    let missing_features: Vec<&str> = REQUIRED_FEATURES
+        .iter()
+        .filter(|(_, field)| !is_supported(&features, *field))
+        .map(|(name, _)| *name)
+        .collect();
+    let (queue_indices, rejection) =
+        match try_get_queue_family_indices(physical_device, instance_raw, window) {
+            Ok(queue_indices) => (Some(queue_indices), None),
+            Err(rejection) => (None, Some(rejection)),
+        };
+    let rejection = if missing_features.is_empty() {
+        rejection
+    } else {
+        Some(format!(
+            "Required features are not supported: {}",
+            missing_features.join(", ")
+        ))
+    };
+    DeviceCandidate {
+        index,
+        name,
+        device_type: properties.device_type,
+        score: DeviceScore {
+            type_rank: DeviceScore::type_rank(properties.device_type),
+            device_local_memory,
+            optional_features,
+        },
+        rejection,
+        physical_device,
+        queue_indices,
+    }
+}
+
+/// 使えないデバイスなら、その理由を返す
+fn try_get_queue_family_indices(
+    physical_device: PhysicalDevice,
+    instance_raw: &Instance,
+    window: Option<&ManagedWindow>,
+) -> Result<QueueFamilyIndices, String> {
+    let queue_families =
+        unsafe { instance_raw.get_physical_device_queue_family_properties(physical_device) };
+    let graphics_queue_index = find_graphics_queue_family_index(&queue_families)
+        .ok_or_else(|| "No queue family supports graphics".to_owned())?;
+    if let Some(window) = window {
+        if !check_swapchain_support(instance_raw, &physical_device) {
+            return Err(format!("{:?} is not supported", Swapchain::name()));
+        }
+        let presentation_queue_index =
+            find_presentation_queue_family_index(&queue_families, &physical_device, window)
+                .ok_or_else(|| "No queue family can present to the window".to_owned())?;
+        Ok(QueueFamilyIndices {
+            graphics: graphics_queue_index,
+            presentation: Some(presentation_queue_index),
+        })
+    } else {
+        Ok(QueueFamilyIndices {
+            graphics: graphics_queue_index,
+            presentation: None,
+        })
+    }
+}
+
+fn find_graphics_queue_family_index(queue_families: &[QueueFamilyProperties]) -> Option<u32> {
+    queue_families
+        .iter()
+        .enumerate()
+        .find_map(|(queue_family_index, queue_family)| {
+            queue_family
+                .queue_flags
+                .contains(QueueFlags::GRAPHICS)
+                .then(|| queue_family_index as u32)
+        })
+}
+
+fn check_swapchain_support(instance_raw: &Instance, physical_device: &PhysicalDevice) -> bool {
+    unsafe { instance_raw.enumerate_device_extension_properties(*physical_device) }
+        .map(|exts| {
+            exts.into_iter().any(
+                |ext| unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) } == Swapchain::name(),
+            )
+        })
+        .unwrap_or(false)
+}
+
+fn find_presentation_queue_family_index(
+    queue_families: &[QueueFamilyProperties],
+    physical_device: &PhysicalDevice,
+    window: &ManagedWindow,
+) -> Option<u32> {
+    queue_families
+        .iter()
+        .enumerate()
+        .find_map(|(queue_family_index, _)| {
+            let queue_family_index = queue_family_index as u32;
+            window
+                .get_physical_device_surface_support(&physical_device, queue_family_index)
+                .then(|| queue_family_index)
+        })
+}
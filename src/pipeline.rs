@@ -1,12 +1,621 @@
+//! グラフィックスパイプライン
+
+use crate::{
+    descriptor::ManagedDescriptorSetLayout,
+    render_pass::ManagedRenderPass,
+    shader::{ShaderLibrary, ShaderModuleWrapper},
+    vertex::Vertex,
+};
+use anyhow::Context;
 use ash::{
+    util::read_spv,
     version::DeviceV1_0,
-    vk::{Pipeline, PipelineLayout},
+    vk::{
+        BlendFactor, BlendOp, ColorComponentFlags, CompareOp, CullModeFlags, DescriptorSetLayout,
+        DynamicState, Extent2D, FrontFace, GraphicsPipelineCreateInfo, Offset2D, Pipeline,
+        PipelineColorBlendAttachmentState, PipelineColorBlendStateCreateInfo,
+        PipelineDepthStencilStateCreateInfo, PipelineDynamicStateCreateInfo,
+        PipelineInputAssemblyStateCreateInfo, PipelineLayout, PipelineLayoutCreateInfo,
+        PipelineMultisampleStateCreateInfo, PipelineRasterizationStateCreateInfo,
+        PipelineVertexInputStateCreateInfo, PipelineViewportStateCreateInfo, PolygonMode,
+        PrimitiveTopology, PushConstantRange, Rect2D, SampleCountFlags, ShaderStageFlags,
+        VertexInputAttributeDescription, VertexInputBindingDescription, Viewport,
+    },
     Device,
 };
+use bytemuck::Pod;
+use std::{io::Cursor, mem::size_of};
+
+/// `T` をプッシュ定数として `offset` バイト目から置く範囲
+pub fn push_constant_range<T>(stages: ShaderStageFlags, offset: u32) -> PushConstantRange
+where
+    T: Pod,
+{
+    PushConstantRange::builder()
+        .stage_flags(stages)
+        .offset(offset)
+        .size(size_of::<T>() as u32)
+        .build()
+}
+
+/// プッシュ定数の更新が、レイアウトで宣言した範囲に合っているか調べる
+///
+/// 更新するバイトはどれも `stages` のすべてのステージについて宣言されていなければならず、
+/// 重なる範囲のステージはすべて `stages` に含まれていなければならない
+pub fn validate_push_constants(
+    ranges: &[PushConstantRange],
+    stages: ShaderStageFlags,
+    offset: u32,
+    size: u32,
+) -> anyhow::Result<()> {
+    ensure!(
+        !stages.is_empty(),
+        "Push constants must be updated for at least one stage"
+    );
+    ensure!(
+        (offset | size) & 0b11 == 0 && size > 0,
+        "Push constant offset ({}) and size ({}) must be non-zero multiples of 4",
+        offset,
+        size
+    );
+    let end = offset.checked_add(size).with_context(|| {
+        format!(
+            "Push constant range at offset {} with size {} overflows",
+            offset, size
+        )
+    })?;
+    for range in ranges
+        .iter()
+        .filter(|range| range.offset < end && offset < range.offset + range.size)
+    {
+        ensure!(
+            stages.contains(range.stage_flags),
+            "Push constant range {}..{} is used by {:?}, but only {:?} were given",
+            range.offset,
+            range.offset + range.size,
+            range.stage_flags,
+            stages
+        );
+    }
+    for byte in (offset..end).step_by(4) {
+        let declared = ranges
+            .iter()
+            .filter(|range| range.offset <= byte && byte < range.offset + range.size)
+            .fold(ShaderStageFlags::empty(), |declared, range| {
+                declared | range.stage_flags
+            });
+        ensure!(
+            declared.contains(stages),
+            "Push constant byte {} is not declared for {:?} in the pipeline layout",
+            byte,
+            stages
+        );
+    }
+    Ok(())
+}
+
+/// パイプラインの 1 つのシェーダステージ (エントリポイントは `main`)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderStage {
+    pub stage: ShaderStageFlags,
+    pub code: Vec<u32>,
+}
+
+impl ShaderStage {
+    pub fn new(stage: ShaderStageFlags, code: Vec<u32>) -> ShaderStage {
+        ShaderStage { stage, code }
+    }
+
+    /// SPIR-V バイナリのバイト列から作る
+    pub fn from_spv(stage: ShaderStageFlags, bytes: &[u8]) -> anyhow::Result<ShaderStage> {
+        let code = read_spv(&mut Cursor::new(bytes)).context("Failed to read SPIR-V binary")?;
+        Ok(ShaderStage::new(stage, code))
+    }
+}
+
+/// ブレンドしないカラーアタッチメント
+pub fn opaque_blend_attachment() -> PipelineColorBlendAttachmentState {
+    PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(ColorComponentFlags::all())
+        .blend_enable(false)
+        .build()
+}
+
+/// アルファ値で重ねるカラーアタッチメント (ストレートアルファ)
+pub fn alpha_blend_attachment() -> PipelineColorBlendAttachmentState {
+    PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(ColorComponentFlags::all())
+        .blend_enable(true)
+        .src_color_blend_factor(BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(BlendOp::ADD)
+        .src_alpha_blend_factor(BlendFactor::ONE)
+        .dst_alpha_blend_factor(BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(BlendOp::ADD)
+        .build()
+}
+
+/// グラフィックスパイプラインの設定
+///
+/// 既定値は、三角形リスト・塗りつぶし・背面カリング・時計回りが表・深度テスト無し・ブレンド無しのカラーアタッチメント 1 つ・サブパス 0。
+/// ビューポートとシザーは動的で、描画時に設定する
+///
+/// 指定したディスクリプタセットレイアウトは、ビルダーが生きている間は破棄できない
+#[derive(Clone)]
+pub struct PipelineBuilder<'l> {
+    stages: Vec<ShaderStage>,
+    vertex_bindings: Vec<VertexInputBindingDescription>,
+    vertex_attributes: Vec<VertexInputAttributeDescription>,
+    topology: PrimitiveTopology,
+    primitive_restart: bool,
+    polygon_mode: PolygonMode,
+    cull_mode: CullModeFlags,
+    front_face: FrontFace,
+    line_width: f32,
+    depth_stencil: PipelineDepthStencilStateCreateInfo,
+    blend_attachments: Vec<PipelineColorBlendAttachmentState>,
+    samples: SampleCountFlags,
+    blend_constants: [f32; 4],
+    /// `None` なら、ビューポートとシザーを動的に設定する
+    fixed_extent: Option<Extent2D>,
+    /// ビューポートとシザー以外の動的ステート
+    dynamic_states: Vec<DynamicState>,
+    set_layouts: Vec<&'l ManagedDescriptorSetLayout<'l>>,
+    push_constant_ranges: Vec<PushConstantRange>,
+    subpass: u32,
+}
+
+impl<'l> PipelineBuilder<'l> {
+    pub fn new() -> PipelineBuilder<'l> {
+        PipelineBuilder {
+            stages: Vec::new(),
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            topology: PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart: false,
+            polygon_mode: PolygonMode::FILL,
+            cull_mode: CullModeFlags::BACK,
+            front_face: FrontFace::CLOCKWISE,
+            line_width: 1.0,
+            depth_stencil: PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(false)
+                .depth_write_enable(false)
+                .depth_compare_op(CompareOp::ALWAYS)
+                .min_depth_bounds(0.0)
+                .max_depth_bounds(1.0)
+                .build(),
+            blend_attachments: vec![opaque_blend_attachment()],
+            samples: SampleCountFlags::TYPE_1,
+            blend_constants: [0.0; 4],
+            fixed_extent: None,
+            dynamic_states: Vec::new(),
+            set_layouts: Vec::new(),
+            push_constant_ranges: Vec::new(),
+            subpass: 0,
+        }
+    }
+
+    /// 同じステージを 2 回指定すると、`build` がエラーを返す
+    pub fn stage(mut self, stage: ShaderStage) -> PipelineBuilder<'l> {
+        self.stages.push(stage);
+        self
+    }
+
+    /// 頂点バッファはバインディング 0 に `V` を並べたものとして扱う
+    pub fn vertex_input<V>(self) -> PipelineBuilder<'l>
+    where
+        V: Vertex,
+    {
+        self.vertex_layout(
+            vec![V::binding_description(0)],
+            V::attribute_descriptions(0),
+        )
+    }
+
+    /// 複数のバインディングを使う場合など、頂点入力を直接指定する
+    pub fn vertex_layout(
+        mut self,
+        bindings: Vec<VertexInputBindingDescription>,
+        attributes: Vec<VertexInputAttributeDescription>,
+    ) -> PipelineBuilder<'l> {
+        self.vertex_bindings = bindings;
+        self.vertex_attributes = attributes;
+        self
+    }
+
+    pub fn topology(mut self, topology: PrimitiveTopology) -> PipelineBuilder<'l> {
+        self.topology = topology;
+        self
+    }
+
+    /// インデックスが `u32::MAX` のところでストリップを区切る
+    pub fn primitive_restart(mut self, enable: bool) -> PipelineBuilder<'l> {
+        self.primitive_restart = enable;
+        self
+    }
+
+    /// `FILL` 以外はデバイスの `fillModeNonSolid` 機能が必要 (無ければ `build` がエラーを返す)
+    pub fn polygon_mode(mut self, polygon_mode: PolygonMode) -> PipelineBuilder<'l> {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: CullModeFlags) -> PipelineBuilder<'l> {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: FrontFace) -> PipelineBuilder<'l> {
+        self.front_face = front_face;
+        self
+    }
+
+    /// 1.0 以外はデバイスの `wideLines` 機能が必要 (無ければ `build` がエラーを返す)
+    pub fn line_width(mut self, line_width: f32) -> PipelineBuilder<'l> {
+        self.line_width = line_width;
+        self
+    }
+
+    /// 深度テストを有効にする (レンダーパスに深度アタッチメントが必要)
+    pub fn depth_test(mut self, write: bool, compare_op: CompareOp) -> PipelineBuilder<'l> {
+        self.depth_stencil.depth_test_enable = true.into();
+        self.depth_stencil.depth_write_enable = write.into();
+        self.depth_stencil.depth_compare_op = compare_op;
+        self
+    }
+
+    /// ステンシルテストなども含めて、深度・ステンシルの設定を直接指定する
+    pub fn depth_stencil(
+        mut self,
+        depth_stencil: PipelineDepthStencilStateCreateInfo,
+    ) -> PipelineBuilder<'l> {
+        self.depth_stencil = depth_stencil;
+        self
+    }
+
+    /// サブパスのカラーアタッチメントの順に、それぞれのブレンドの設定
+    pub fn blend_attachments(
+        mut self,
+        attachments: Vec<PipelineColorBlendAttachmentState>,
+    ) -> PipelineBuilder<'l> {
+        self.blend_attachments = attachments;
+        self
+    }
+
+    pub fn samples(mut self, samples: SampleCountFlags) -> PipelineBuilder<'l> {
+        self.samples = samples;
+        self
+    }
+
+    /// `BlendFactor::CONSTANT_COLOR` などで使う色
+    pub fn blend_constants(mut self, blend_constants: [f32; 4]) -> PipelineBuilder<'l> {
+        self.blend_constants = blend_constants;
+        self
+    }
+
+    /// ビューポートとシザーを、動的に設定せずに `width` x `height` 全体に固定する
+    pub fn fixed_viewport(mut self, width: u32, height: u32) -> PipelineBuilder<'l> {
+        self.fixed_extent = Some(Extent2D { width, height });
+        self
+    }
 
+    /// 描画時に設定するステートを追加する (`LINE_WIDTH` や `BLEND_CONSTANTS` など)
+    ///
+    /// ビューポートとシザーは、`fixed_viewport` を呼ばなければ常に動的になる
+    pub fn dynamic_state(mut self, state: DynamicState) -> PipelineBuilder<'l> {
+        if state != DynamicState::VIEWPORT
+            && state != DynamicState::SCISSOR
+            && !self.dynamic_states.contains(&state)
+        {
+            self.dynamic_states.push(state);
+        }
+        self
+    }
+
+    /// 順に、シェーダの `set = 0, 1, ...` に対応する
+    pub fn descriptor_set_layouts(
+        mut self,
+        set_layouts: &[&'l ManagedDescriptorSetLayout<'l>],
+    ) -> PipelineBuilder<'l> {
+        self.set_layouts = set_layouts.to_vec();
+        self
+    }
+
+    /// 範囲は `push_constant_range` で作る
+    pub fn push_constant_ranges(mut self, ranges: &[PushConstantRange]) -> PipelineBuilder<'l> {
+        self.push_constant_ranges = ranges.to_vec();
+        self
+    }
+
+    pub fn subpass(mut self, subpass: u32) -> PipelineBuilder<'l> {
+        self.subpass = subpass;
+        self
+    }
+
+    /// `render_pass` の中で使うパイプラインを作る
+    pub fn build<'a>(
+        &self,
+        render_pass: &ManagedRenderPass<'a>,
+    ) -> anyhow::Result<ManagedPipeline<'a>> {
+        let device = render_pass.get_device();
+        ensure!(
+            self.stages
+                .iter()
+                .any(|stage| stage.stage == ShaderStageFlags::VERTEX),
+            "Graphics pipeline needs a vertex shader"
+        );
+        for (i, stage) in self.stages.iter().enumerate() {
+            ensure!(
+                self.stages[..i]
+                    .iter()
+                    .all(|other| other.stage != stage.stage),
+                "Shader stage {:?} is specified more than once",
+                stage.stage
+            );
+        }
+        let enabled_features = render_pass.get_enabled_features();
+        ensure!(
+            self.polygon_mode == PolygonMode::FILL || enabled_features.fill_mode_non_solid != 0,
+            "Polygon mode {:?} requires the fillModeNonSolid feature",
+            self.polygon_mode
+        );
+        // 線の太さが動的なら、ここで指定した値は使われない
+        ensure!(
+            self.line_width == 1.0
+                || self.dynamic_states.contains(&DynamicState::LINE_WIDTH)
+                || enabled_features.wide_lines != 0,
+            "Line width {} requires the wideLines feature",
+            self.line_width
+        );
+        let color_attachment_count = render_pass
+            .get_color_attachment_count(self.subpass)
+            .with_context(|| format!("Render pass has no subpass {}", self.subpass))?;
+        ensure!(
+            self.blend_attachments.len() as u32 == color_attachment_count,
+            "{} blend attachments were given, but subpass {} has {} color attachments",
+            self.blend_attachments.len(),
+            self.subpass,
+            color_attachment_count
+        );
+        ensure!(
+            self.samples == render_pass.get_samples(),
+            "Sample count {:?} does not match the render pass ({:?})",
+            self.samples,
+            render_pass.get_samples()
+        );
+        let mut dynamic_states = self.dynamic_states.clone();
+        // 動的な場合も、ビューポートとシザーの数は指定する必要がある (値は無視される)
+        let extent = match self.fixed_extent {
+            Some(extent) => extent,
+            None => {
+                dynamic_states.extend_from_slice(&[DynamicState::VIEWPORT, DynamicState::SCISSOR]);
+                Extent2D::default()
+            }
+        };
+        let viewport = full_viewport(extent.width, extent.height);
+        let scissor = full_scissor(extent.width, extent.height);
+        let viewport_state = PipelineViewportStateCreateInfo::builder()
+            .viewports(&[viewport])
+            .scissors(&[scissor])
+            .build();
+        let dynamic_state = PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&dynamic_states)
+            .build();
+        let vertex_input_info = PipelineVertexInputStateCreateInfo::builder()
+            .vertex_attribute_descriptions(&self.vertex_attributes)
+            .vertex_binding_descriptions(&self.vertex_bindings)
+            .build();
+        let input_assembly = PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(self.topology)
+            .primitive_restart_enable(self.primitive_restart)
+            .build();
+        let rasterizer = PipelineRasterizationStateCreateInfo::builder()
+            .depth_clamp_enable(false)
+            .rasterizer_discard_enable(false)
+            .polygon_mode(self.polygon_mode)
+            .line_width(self.line_width)
+            .cull_mode(self.cull_mode)
+            .front_face(self.front_face)
+            .depth_bias_enable(false)
+            .build();
+        let multisample = PipelineMultisampleStateCreateInfo::builder()
+            .sample_shading_enable(false)
+            .rasterization_samples(self.samples)
+            .build();
+        let blend = PipelineColorBlendStateCreateInfo::builder()
+            .logic_op_enable(false)
+            .attachments(&self.blend_attachments)
+            .blend_constants(self.blend_constants)
+            .build();
+        let shader_modules = self
+            .stages
+            .iter()
+            .map(|stage| ShaderModuleWrapper::new(device, &stage.code, stage.stage))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let shader_stages: Vec<_> = shader_modules
+            .iter()
+            .map(|module| module.create_stage())
+            .collect();
+        let set_layouts: Vec<DescriptorSetLayout> = self
+            .set_layouts
+            .iter()
+            .map(|layout| layout.get_descriptor_set_layout_raw())
+            .collect();
+        let layout_create_info = PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&self.push_constant_ranges)
+            .build();
+        let pipeline_layout = unsafe { device.create_pipeline_layout(&layout_create_info, None) }
+            .context("Failed to create PipelineLayout")?;
+        let create_info = GraphicsPipelineCreateInfo::builder()
+            .viewport_state(&viewport_state)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly)
+            .rasterization_state(&rasterizer)
+            .multisample_state(&multisample)
+            .depth_stencil_state(&self.depth_stencil)
+            .color_blend_state(&blend)
+            .dynamic_state(&dynamic_state)
+            .layout(pipeline_layout)
+            .stages(&shader_stages)
+            .render_pass(render_pass.get_render_pass_raw())
+            .subpass(self.subpass)
+            .build();
+        let pipeline = match unsafe {
+            device.create_graphics_pipelines(
+                render_pass.get_pipeline_cache_raw(),
+                &[create_info],
+                None,
+            )
+        } {
+            Ok(pipelines) => pipelines[0],
+            Err((_, err)) => {
+                unsafe { device.destroy_pipeline_layout(pipeline_layout, None) };
+                return Err(err).context("Failed to create graphics pipeline");
+            }
+        };
+        Ok(ManagedPipeline::new(
+            device,
+            pipeline_layout,
+            self.set_layouts.len() as u32,
+            self.push_constant_ranges.clone(),
+            dynamic_states,
+            pipeline,
+        ))
+    }
+}
+
+/// シェーダが変更されたら作り直せるように、シェーダの名前と設定を覚えておくパイプライン
+pub struct ReloadablePipeline<'a, 'l> {
+    /// シェーダステージ以外の設定
+    builder: PipelineBuilder<'l>,
+    shaders: Vec<(ShaderStageFlags, String)>,
+    pipeline: ManagedPipeline<'a>,
+}
+
+impl<'a, 'l> ReloadablePipeline<'a, 'l> {
+    /// `shaders` は `ShaderLibrary::load` に渡すシェーダの名前とステージ
+    pub fn new(
+        library: &ShaderLibrary,
+        builder: PipelineBuilder<'l>,
+        shaders: &[(ShaderStageFlags, &str)],
+        render_pass: &ManagedRenderPass<'a>,
+    ) -> anyhow::Result<ReloadablePipeline<'a, 'l>> {
+        let shaders: Vec<(ShaderStageFlags, String)> = shaders
+            .iter()
+            .map(|&(stage, name)| (stage, name.to_owned()))
+            .collect();
+        let pipeline = build_with_shaders(library, &builder, &shaders, render_pass)?;
+        Ok(ReloadablePipeline {
+            builder,
+            shaders,
+            pipeline,
+        })
+    }
+
+    pub fn get_pipeline(&self) -> &ManagedPipeline<'a> {
+        &self.pipeline
+    }
+
+    pub fn uses_shader(&self, name: &str) -> bool {
+        self.shaders.iter().any(|(_, shader)| shader == name)
+    }
+
+    /// シェーダを読み込み直してパイプラインを作り直す
+    ///
+    /// 失敗した場合は、元のパイプラインをそのまま使い続ける。
+    /// 置き換える前にデバイスの処理が終わるのを待つので、描画中のフレームがあってもよい
+    pub fn rebuild(
+        &mut self,
+        library: &ShaderLibrary,
+        render_pass: &ManagedRenderPass<'a>,
+    ) -> anyhow::Result<()> {
+        let pipeline = build_with_shaders(library, &self.builder, &self.shaders, render_pass)?;
+        unsafe { render_pass.get_device().device_wait_idle() }
+            .context("Failed to wait for device idle")?;
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
+    /// `changed` のシェーダを使っていれば作り直し、作り直したかどうかを返す
+    ///
+    /// 作り直しに失敗した場合はエラーをログに出して、元のパイプラインを使い続ける
+    pub fn reload_if_changed(
+        &mut self,
+        library: &ShaderLibrary,
+        render_pass: &ManagedRenderPass<'a>,
+        changed: &[String],
+    ) -> bool {
+        if !changed.iter().any(|name| self.uses_shader(name)) {
+            return false;
+        }
+        match self.rebuild(library, render_pass) {
+            Ok(()) => {
+                info!("Reloaded pipeline with shaders {:?}", self.shader_names());
+                true
+            }
+            Err(err) => {
+                error!(
+                    "Failed to reload shaders, keeping the previous pipeline: {:?}",
+                    err
+                );
+                false
+            }
+        }
+    }
+
+    fn shader_names(&self) -> Vec<&str> {
+        self.shaders.iter().map(|(_, name)| name.as_str()).collect()
+    }
+}
+
+fn build_with_shaders<'a>(
+    library: &ShaderLibrary,
+    builder: &PipelineBuilder<'_>,
+    shaders: &[(ShaderStageFlags, String)],
+    render_pass: &ManagedRenderPass<'a>,
+) -> anyhow::Result<ManagedPipeline<'a>> {
+    let mut builder = builder.clone();
+    for (stage, name) in shaders {
+        builder = builder.stage(library.load(name, *stage)?);
+    }
+    builder.build(render_pass)
+}
+
+impl Default for PipelineBuilder<'_> {
+    fn default() -> Self {
+        PipelineBuilder::new()
+    }
+}
+
+/// `width` x `height` 全体を覆うビューポート (深度は 0..1)
+pub fn full_viewport(width: u32, height: u32) -> Viewport {
+    Viewport {
+        x: 0.0,
+        y: 0.0,
+        min_depth: 0.0,
+        max_depth: 1.0,
+        width: width as f32,
+        height: height as f32,
+    }
+}
+
+/// `width` x `height` 全体を覆うシザー
+pub fn full_scissor(width: u32, height: u32) -> Rect2D {
+    Rect2D {
+        offset: Offset2D { x: 0, y: 0 },
+        extent: Extent2D { width, height },
+    }
+}
+
+/// 自動で解放される、パイプラインとそのパイプラインレイアウト
 pub struct ManagedPipeline<'a> {
     device: &'a Device,
     pipeline_layout: PipelineLayout,
+    /// レイアウトに含まれるディスクリプタセットレイアウトの数
+    set_layout_count: u32,
+    push_constant_ranges: Vec<PushConstantRange>,
+    dynamic_states: Vec<DynamicState>,
     pipeline_raw: Pipeline,
 }
 
@@ -14,11 +623,17 @@ impl<'a> ManagedPipeline<'a> {
     pub fn new(
         device: &'a Device,
         pipeline_layout: PipelineLayout,
+        set_layout_count: u32,
+        push_constant_ranges: Vec<PushConstantRange>,
+        dynamic_states: Vec<DynamicState>,
         pipeline_raw: Pipeline,
     ) -> ManagedPipeline<'a> {
         ManagedPipeline {
             device,
             pipeline_layout,
+            set_layout_count,
+            push_constant_ranges,
+            dynamic_states,
             pipeline_raw,
         }
     }
@@ -26,6 +641,23 @@ impl<'a> ManagedPipeline<'a> {
     pub fn get_pipeline_raw(&self) -> Pipeline {
         self.pipeline_raw
     }
+
+    pub fn get_pipeline_layout_raw(&self) -> PipelineLayout {
+        self.pipeline_layout
+    }
+
+    pub fn get_set_layout_count(&self) -> u32 {
+        self.set_layout_count
+    }
+
+    pub fn get_push_constant_ranges(&self) -> &[PushConstantRange] {
+        &self.push_constant_ranges
+    }
+
+    /// `state` を描画時に設定するパイプラインかどうか
+    pub fn has_dynamic_state(&self, state: DynamicState) -> bool {
+        self.dynamic_states.contains(&state)
+    }
 }
 
 impl Drop for ManagedPipeline<'_> {
@@ -0,0 +1,202 @@
+//! ディスクに保存するパイプラインキャッシュ
+
+use anyhow::Context;
+use ash::{
+    version::DeviceV1_0,
+    vk::{PhysicalDeviceProperties, PipelineCache, PipelineCacheCreateInfo, UUID_SIZE},
+    Device,
+};
+use std::{
+    convert::TryInto,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// キャッシュファイルの場所を上書きする環境変数 (空にすると保存しない)
+pub const PIPELINE_CACHE_ENV_VAR: &str = "GAME_PIPELINE_CACHE";
+
+/// `VK_PIPELINE_CACHE_HEADER_VERSION_ONE` のヘッダの長さ
+const HEADER_SIZE: usize = 16 + UUID_SIZE;
+const HEADER_VERSION_ONE: u32 = 1;
+
+/// 環境変数か、ユーザのキャッシュディレクトリから決めたキャッシュファイルの場所
+pub fn default_path() -> Option<PathBuf> {
+    match std::env::var_os(PIPELINE_CACHE_ENV_VAR) {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => dirs::cache_dir().map(|dir| dir.join("game").join("pipeline_cache.bin")),
+    }
+}
+
+/// パイプラインキャッシュのデータの先頭にあるヘッダ
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PipelineCacheHeader {
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub cache_uuid: [u8; UUID_SIZE],
+}
+
+impl PipelineCacheHeader {
+    /// 今のデバイスが作るキャッシュのヘッダ
+    pub fn from_properties(properties: &PhysicalDeviceProperties) -> PipelineCacheHeader {
+        PipelineCacheHeader {
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            cache_uuid: properties.pipeline_cache_uuid,
+        }
+    }
+
+    /// 知らない形式のヘッダや、短すぎるデータなら `None` を返す
+    pub fn parse(data: &[u8]) -> Option<PipelineCacheHeader> {
+        if data.len() < HEADER_SIZE {
+            return None;
+        }
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        let header_size = read_u32(0) as usize;
+        if header_size < HEADER_SIZE
+            || header_size > data.len()
+            || read_u32(4) != HEADER_VERSION_ONE
+        {
+            return None;
+        }
+        Some(PipelineCacheHeader {
+            vendor_id: read_u32(8),
+            device_id: read_u32(12),
+            cache_uuid: data[16..HEADER_SIZE].try_into().unwrap(),
+        })
+    }
+}
+
+/// ファイルから読み込み、破棄するときにファイルへ書き戻すパイプラインキャッシュ
+///
+/// 論理デバイスより先に破棄しなければならないので、`ManagedLogicalDevice` だけが作って `destroy` を呼ぶ
+pub struct ManagedPipelineCache {
+    device: Device,
+    cache_raw: PipelineCache,
+    /// `None` なら保存しない
+    path: Option<PathBuf>,
+}
+
+impl ManagedPipelineCache {
+    /// `path` のキャッシュが今のデバイスのものでなければ、空のキャッシュから始める
+    pub(crate) fn new(
+        device: Device,
+        properties: &PhysicalDeviceProperties,
+        path: Option<PathBuf>,
+    ) -> anyhow::Result<ManagedPipelineCache> {
+        let initial_data = match path.as_deref() {
+            Some(path) => {
+                load_initial_data(path, &PipelineCacheHeader::from_properties(properties))
+            }
+            None => Vec::new(),
+        };
+        let create_info = PipelineCacheCreateInfo::builder()
+            .initial_data(&initial_data)
+            .build();
+        let cache_raw = match unsafe { device.create_pipeline_cache(&create_info, None) } {
+            Ok(cache_raw) => cache_raw,
+            // ヘッダが合っていても中身が壊れている場合に備えて、空のキャッシュでやり直す
+            Err(err) if !initial_data.is_empty() => {
+                warn!("Discarded pipeline cache ({:?})", err);
+                let create_info = PipelineCacheCreateInfo::builder().build();
+                unsafe { device.create_pipeline_cache(&create_info, None) }
+                    .context("Failed to create PipelineCache")?
+            }
+            Err(err) => return Err(err).context("Failed to create PipelineCache"),
+        };
+        Ok(ManagedPipelineCache {
+            device,
+            cache_raw,
+            path,
+        })
+    }
+
+    pub fn get_pipeline_cache_raw(&self) -> PipelineCache {
+        self.cache_raw
+    }
+
+    pub fn get_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// 今のキャッシュの内容 (ヘッダを含む)
+    pub fn get_data(&self) -> anyhow::Result<Vec<u8>> {
+        unsafe { self.device.get_pipeline_cache_data(self.cache_raw) }
+            .context("Failed to get pipeline cache data")
+    }
+
+    /// 今のキャッシュの内容をファイルに書き込む
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = match self.path.as_deref() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let data = self.get_data()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        // 書き込み途中で終了しても壊れたファイルが残らないように、別名で書いてから置き換える
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, &data)
+            .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+        fs::rename(&temp_path, path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        debug!(
+            "Saved pipeline cache ({} bytes) to {}",
+            data.len(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// ファイルに書き戻してから破棄する (これ以降は使えない)
+    pub(crate) fn destroy(&self) {
+        if let Err(err) = self.save() {
+            warn!("{:?}", err);
+        }
+        unsafe { self.device.destroy_pipeline_cache(self.cache_raw, None) };
+        trace!("PipelineCache was destroyed");
+    }
+}
+
+/// 読み込めないファイルや、別のデバイスのキャッシュは使わない
+fn load_initial_data(path: &Path, expected: &PipelineCacheHeader) -> Vec<u8> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            debug!("Pipeline cache {} does not exist yet", path.display());
+            return Vec::new();
+        }
+        Err(err) => {
+            warn!("Failed to read pipeline cache {}: {}", path.display(), err);
+            return Vec::new();
+        }
+    };
+    match PipelineCacheHeader::parse(&data) {
+        Some(header) if header == *expected => {
+            debug!(
+                "Loaded pipeline cache ({} bytes) from {}",
+                data.len(),
+                path.display()
+            );
+            data
+        }
+        Some(_) => {
+            info!(
+                "Pipeline cache {} was created by another device or driver, ignoring it",
+                path.display()
+            );
+            Vec::new()
+        }
+        None => {
+            warn!(
+                "Pipeline cache {} is corrupted, ignoring it",
+                path.display()
+            );
+            Vec::new()
+        }
+    }
+}
@@ -1,40 +1,53 @@
 use crate::{
-    pipeline::ManagedPipeline,
-    shader::{ShaderModuleWrapper, FRAG_SHADER, VERT_SHADER},
+    descriptor::ManagedDescriptorSetLayout,
+    pipeline::{ManagedPipeline, PipelineBuilder},
+    shader::{ShaderLibrary, DEFAULT_FRAGMENT_SHADER, DEFAULT_VERTEX_SHADER},
+    vertex::Vertex,
 };
 use anyhow::Context;
 use ash::{
     version::DeviceV1_0,
     vk::{
-        AttachmentDescription, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp,
-        ColorComponentFlags, CullModeFlags, Extent2D, Format, FrontFace,
-        GraphicsPipelineCreateInfo, ImageLayout, Offset2D, PipelineBindPoint, PipelineCache,
-        PipelineColorBlendAttachmentState, PipelineColorBlendStateCreateInfo,
-        PipelineInputAssemblyStateCreateInfo, PipelineLayoutCreateInfo,
-        PipelineMultisampleStateCreateInfo, PipelineRasterizationStateCreateInfo,
-        PipelineVertexInputStateCreateInfo, PipelineViewportStateCreateInfo, PolygonMode,
-        PrimitiveTopology, Rect2D, RenderPass, RenderPassCreateInfo, SampleCountFlags,
-        SubpassDescription, Viewport,
+        AccessFlags, AttachmentDescription, AttachmentLoadOp, AttachmentReference,
+        AttachmentStoreOp, Format, ImageLayout, PhysicalDeviceFeatures, PipelineBindPoint,
+        PipelineCache, PipelineStageFlags, PushConstantRange, RenderPass, RenderPassCreateInfo,
+        SampleCountFlags, ShaderStageFlags, SubpassDependency, SubpassDescription,
+        SUBPASS_EXTERNAL,
     },
     Device,
 };
 
 pub struct ManagedRenderPass<'a> {
     device: &'a Device,
+    /// このレンダーパスで使うパイプラインを作るときに渡す
+    pipeline_cache: PipelineCache,
+    /// パイプラインの設定が使える機能かどうかを調べるために持っておく
+    enabled_features: PhysicalDeviceFeatures,
+    samples: SampleCountFlags,
+    /// サブパスごとのカラーアタッチメントの数
+    color_attachment_counts: Vec<u32>,
     render_pass_raw: RenderPass,
 }
 
 impl<'a> ManagedRenderPass<'a> {
-    pub fn new(device: &'a Device) -> anyhow::Result<ManagedRenderPass<'a>> {
+    /// `final_layout` は、描画後にカラーアタッチメントを遷移させるレイアウト
+    pub fn new(
+        device: &'a Device,
+        pipeline_cache: PipelineCache,
+        enabled_features: PhysicalDeviceFeatures,
+        format: Format,
+        final_layout: ImageLayout,
+    ) -> anyhow::Result<ManagedRenderPass<'a>> {
+        let samples = SampleCountFlags::TYPE_1;
         let attachment_desc = AttachmentDescription::builder()
-            .format(Format::R8G8B8A8_UNORM)
-            .samples(SampleCountFlags::TYPE_1)
+            .format(format)
+            .samples(samples)
             .load_op(AttachmentLoadOp::CLEAR)
             .store_op(AttachmentStoreOp::STORE)
             .stencil_load_op(AttachmentLoadOp::DONT_CARE)
             .stencil_store_op(AttachmentStoreOp::DONT_CARE)
             .initial_layout(ImageLayout::UNDEFINED)
-            .final_layout(ImageLayout::GENERAL)
+            .final_layout(final_layout)
             .build();
         let attachment_ref = AttachmentReference::builder()
             .attachment(0)
@@ -44,106 +57,76 @@ impl<'a> ManagedRenderPass<'a> {
             .pipeline_bind_point(PipelineBindPoint::GRAPHICS)
             .color_attachments(&[attachment_ref])
             .build();
+        // スワップチェーンのイメージが取得されるまでカラーアタッチメントへの書き込みを待つ
+        let dependency = SubpassDependency::builder()
+            .src_subpass(SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(AccessFlags::empty())
+            .dst_stage_mask(PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .dst_access_mask(AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .build();
         let create_info = RenderPassCreateInfo::builder()
             .attachments(&[attachment_desc])
             .subpasses(&[subpass])
-            .dependencies(&[])
+            .dependencies(&[dependency])
             .build();
         let render_pass_raw = unsafe { device.create_render_pass(&create_info, None) }
             .context("Failed to create RenderPass")?;
         Ok(ManagedRenderPass {
             device,
+            pipeline_cache,
+            enabled_features,
+            samples,
+            color_attachment_counts: vec![1],
             render_pass_raw,
         })
     }
 
-    pub fn create_graphics_pipeline(
+    /// シェーダディレクトリの `vert.spv` と `frag.spv` で、頂点バッファのバインディング 0 に `V` を並べたものを描画するパイプラインを作る
+    ///
+    /// ビューポートとシザーは動的なので、描画先の大きさが変わっても作り直さなくてよい。
+    /// `set_layouts` の順に、シェーダの `set = 0, 1, ...` に対応する。
+    /// プッシュ定数の範囲は `pipeline::push_constant_range` で作る。
+    /// それ以外の設定を変えたい場合は `PipelineBuilder` を使う
+    pub fn create_graphics_pipeline<V>(
         &self,
-        width: u32,
-        height: u32,
-    ) -> anyhow::Result<ManagedPipeline> {
-        let viewport = Viewport {
-            x: 0.0,
-            y: 0.0,
-            min_depth: 0.0,
-            max_depth: 1.0,
-            width: width as f32,
-            height: height as f32,
-        };
-        let scissor = Rect2D {
-            offset: Offset2D { x: 0, y: 0 },
-            extent: Extent2D { width, height },
-        };
-        let viewport_state = PipelineViewportStateCreateInfo::builder()
-            .viewports(&[viewport])
-            .scissors(&[scissor])
-            .build();
-        let vertex_input_info = PipelineVertexInputStateCreateInfo::builder()
-            .vertex_attribute_descriptions(&[])
-            .vertex_binding_descriptions(&[])
-            .build();
-        let input_assembly = PipelineInputAssemblyStateCreateInfo::builder()
-            .topology(PrimitiveTopology::TRIANGLE_LIST)
-            .primitive_restart_enable(false)
-            .build();
-        let rasterizer = PipelineRasterizationStateCreateInfo::builder()
-            .depth_clamp_enable(false)
-            .rasterizer_discard_enable(false)
-            .polygon_mode(PolygonMode::FILL)
-            .line_width(1.0f32)
-            .cull_mode(CullModeFlags::BACK)
-            .front_face(FrontFace::CLOCKWISE)
-            .depth_bias_enable(false)
-            .build();
-        let multisample = PipelineMultisampleStateCreateInfo::builder()
-            .sample_shading_enable(false)
-            .rasterization_samples(SampleCountFlags::TYPE_1)
-            .build();
-        let blend_attachment = PipelineColorBlendAttachmentState::builder()
-            .color_write_mask(
-                ColorComponentFlags::R
-                    | ColorComponentFlags::G
-                    | ColorComponentFlags::B
-                    | ColorComponentFlags::A,
-            )
-            .blend_enable(false)
-            .build();
-        let blend = PipelineColorBlendStateCreateInfo::builder()
-            .logic_op_enable(false)
-            .attachments(&[blend_attachment])
-            .build();
-        let layout_create_info = PipelineLayoutCreateInfo::builder().set_layouts(&[]).build();
-        let pipeline_layout = unsafe {
-            self.device
-                .create_pipeline_layout(&layout_create_info, None)
-        }?;
-        let vert_shader = ShaderModuleWrapper::new(&self.device, &VERT_SHADER.0, VERT_SHADER.1)?;
-        let vert_shader_stage = vert_shader.create_stage();
-        let frag_shader = ShaderModuleWrapper::new(&self.device, &FRAG_SHADER.0, FRAG_SHADER.1)?;
-        let frag_shader_stage = frag_shader.create_stage();
-        let create_info = GraphicsPipelineCreateInfo::builder()
-            .viewport_state(&viewport_state)
-            .vertex_input_state(&vertex_input_info)
-            .input_assembly_state(&input_assembly)
-            .rasterization_state(&rasterizer)
-            .multisample_state(&multisample)
-            .color_blend_state(&blend)
-            .layout(pipeline_layout)
-            .stages(&[vert_shader_stage, frag_shader_stage])
-            .render_pass(self.render_pass_raw)
-            .subpass(0)
-            .build();
-        if let Ok(pipelines) = unsafe {
-            self.device
-                .create_graphics_pipelines(PipelineCache::null(), &[create_info], None)
-        } {
-            let pipeline = *pipelines
-                .first()
-                .context("Failed to create graphics pipeline")?;
-            Ok(ManagedPipeline::new(self.device, pipeline_layout, pipeline))
-        } else {
-            bail!("Failed to create graphics pipeline")
-        }
+        library: &ShaderLibrary,
+        set_layouts: &[&ManagedDescriptorSetLayout],
+        push_constant_ranges: &[PushConstantRange],
+    ) -> anyhow::Result<ManagedPipeline<'a>>
+    where
+        V: Vertex,
+    {
+        PipelineBuilder::new()
+            .stage(library.load(DEFAULT_VERTEX_SHADER, ShaderStageFlags::VERTEX)?)
+            .stage(library.load(DEFAULT_FRAGMENT_SHADER, ShaderStageFlags::FRAGMENT)?)
+            .vertex_input::<V>()
+            .descriptor_set_layouts(set_layouts)
+            .push_constant_ranges(push_constant_ranges)
+            .build(self)
+    }
+
+    pub(crate) fn get_device(&self) -> &'a Device {
+        self.device
+    }
+
+    pub(crate) fn get_pipeline_cache_raw(&self) -> PipelineCache {
+        self.pipeline_cache
+    }
+
+    pub(crate) fn get_enabled_features(&self) -> &PhysicalDeviceFeatures {
+        &self.enabled_features
+    }
+
+    /// アタッチメントのサンプル数
+    pub fn get_samples(&self) -> SampleCountFlags {
+        self.samples
+    }
+
+    /// `subpass` のカラーアタッチメントの数 (そのサブパスが無ければ `None`)
+    pub fn get_color_attachment_count(&self, subpass: u32) -> Option<u32> {
+        self.color_attachment_counts.get(subpass as usize).copied()
     }
 
     pub fn get_render_pass_raw(&self) -> RenderPass {
@@ -1,37 +1,140 @@
+//! シェーダの読み込みと、変更の監視
+
+use crate::pipeline::ShaderStage;
 use anyhow::Context;
 use ash::{
-    util::read_spv,
     version::DeviceV1_0,
     vk::{PipelineShaderStageCreateInfo, ShaderModule, ShaderModuleCreateInfo, ShaderStageFlags},
     Device,
 };
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
 use once_cell::sync::Lazy;
 use std::{
     ffi::{CStr, CString},
-    io::Cursor,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, TryRecvError},
+    time::Duration,
 };
 
-static VERT_SPV: &[u8] = include_bytes!("../shaders/vert.spv");
+/// シェーダのディレクトリを上書きする環境変数
+pub const SHADER_DIR_ENV_VAR: &str = "GAME_SHADER_DIR";
+
+/// 組み込みのパイプラインで使う頂点シェーダ
+pub const DEFAULT_VERTEX_SHADER: &str = "vert.spv";
+
+/// 組み込みのパイプラインで使うフラグメントシェーダ
+pub const DEFAULT_FRAGMENT_SHADER: &str = "frag.spv";
+
+/// ファイルの書き込みが続いている間は、変更を通知しない
+const WATCH_DELAY: Duration = Duration::from_millis(200);
+
+/// 環境変数か、カレントディレクトリの `shaders`
+pub fn default_dir() -> PathBuf {
+    std::env::var_os(SHADER_DIR_ENV_VAR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("shaders"))
+}
+
+/// ディレクトリに置いた SPIR-V バイナリを実行時に読み込む
+pub struct ShaderLibrary {
+    dir: PathBuf,
+    /// `watch` を呼ぶまでは `None`
+    watcher: Option<(RecommendedWatcher, Receiver<DebouncedEvent>)>,
+}
+
+impl ShaderLibrary {
+    pub fn new<P>(dir: P) -> ShaderLibrary
+    where
+        P: Into<PathBuf>,
+    {
+        ShaderLibrary {
+            dir: dir.into(),
+            watcher: None,
+        }
+    }
+
+    pub fn get_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// `name` はディレクトリからの相対パス (例えば `vert.spv`)
+    pub fn load(&self, name: &str, stage: ShaderStageFlags) -> anyhow::Result<ShaderStage> {
+        let path = self.dir.join(name);
+        let bytes = fs::read(&path).with_context(|| {
+            format!(
+                "Failed to read shader {} (compile shaders with `ninja` in the shaders directory)",
+                path.display()
+            )
+        })?;
+        ShaderStage::from_spv(stage, &bytes)
+            .with_context(|| format!("Failed to load shader {}", path.display()))
+    }
 
-static FRAG_SPV: &[u8] = include_bytes!("../shaders/frag.spv");
+    /// ディレクトリの監視を始める (変更は `poll_changes` で受け取る)
+    pub fn watch(&mut self) -> anyhow::Result<()> {
+        let (sender, receiver) = channel();
+        let mut watcher = notify::watcher(sender, WATCH_DELAY)
+            .context("Failed to create file watcher for shaders")?;
+        watcher
+            .watch(&self.dir, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {}", self.dir.display()))?;
+        info!("Watching shaders in {}", self.dir.display());
+        self.watcher = Some((watcher, receiver));
+        Ok(())
+    }
 
-pub static VERT_SHADER: Lazy<(Vec<u32>, ShaderStageFlags)> = Lazy::new(|| {
-    (
-        read_spv(&mut Cursor::new(VERT_SPV)).unwrap(),
-        ShaderStageFlags::VERTEX,
-    )
-});
+    /// 前回呼んでから変更された `.spv` ファイルの名前 (`load` に渡すもの) を返す
+    pub fn poll_changes(&mut self) -> Vec<String> {
+        let mut changed = Vec::new();
+        let receiver = match &self.watcher {
+            Some((_, receiver)) => receiver,
+            None => return changed,
+        };
+        loop {
+            let path = match receiver.try_recv() {
+                Ok(DebouncedEvent::Create(path))
+                | Ok(DebouncedEvent::Write(path))
+                | Ok(DebouncedEvent::Rename(_, path)) => path,
+                Ok(DebouncedEvent::Error(err, path)) => {
+                    warn!("Error while watching shaders ({:?}): {}", path, err);
+                    continue;
+                }
+                Ok(_) => continue,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    warn!("Shader watcher stopped");
+                    self.watcher = None;
+                    break;
+                }
+            };
+            if path.extension() != Some("spv".as_ref()) {
+                continue;
+            }
+            if let Some(name) = self.name_of(&path) {
+                if !changed.contains(&name) {
+                    debug!("Shader {} was changed", name);
+                    changed.push(name);
+                }
+            }
+        }
+        changed
+    }
 
-pub static FRAG_SHADER: Lazy<(Vec<u32>, ShaderStageFlags)> = Lazy::new(|| {
-    (
-        read_spv(&mut Cursor::new(FRAG_SPV)).unwrap(),
-        ShaderStageFlags::FRAGMENT,
-    )
-});
+    /// 監視で得た絶対パスを、ディレクトリからの相対パスに直す
+    fn name_of(&self, path: &Path) -> Option<String> {
+        let dir = fs::canonicalize(&self.dir).unwrap_or_else(|_| self.dir.clone());
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        path.strip_prefix(&dir)
+            .ok()
+            .and_then(|name| name.to_str())
+            .map(|name| name.replace('\\', "/"))
+    }
+}
 
 static STAGE_NAME: Lazy<CString> = Lazy::new(|| CString::new("main").unwrap());
 
-pub struct ShaderModuleWrapper<'a> {
+pub(crate) struct ShaderModuleWrapper<'a> {
     logical_device: &'a Device,
     shader_module_raw: ShaderModule,
     shader_stage_flags: ShaderStageFlags,
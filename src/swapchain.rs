@@ -0,0 +1,254 @@
+use crate::{
+    framebuffer::{AttachmentView, ManagedFramebuffer},
+    logical_device::QueueFamilyIndices,
+    render_pass::ManagedRenderPass,
+    window::ManagedWindow,
+};
+use anyhow::Context;
+use ash::{
+    extensions::khr::Swapchain,
+    version::DeviceV1_0,
+    vk::{
+        ColorSpaceKHR, ComponentMapping, ComponentSwizzle, CompositeAlphaFlagsKHR, Extent2D, Fence,
+        Format, Image, ImageAspectFlags, ImageSubresourceRange, ImageUsageFlags, ImageView,
+        ImageViewCreateInfo, ImageViewType, PhysicalDevice, PresentInfoKHR, PresentModeKHR, Queue,
+        Semaphore, SharingMode, SurfaceCapabilitiesKHR, SurfaceFormatKHR, SwapchainCreateInfoKHR,
+        SwapchainKHR,
+    },
+    Device, Instance,
+};
+
+/// 自動で解放される、スワップチェーンとそのイメージビューのラッパー
+pub struct ManagedSwapchain<'a> {
+    device: &'a Device,
+    swapchain_loader: Swapchain,
+    swapchain_raw: SwapchainKHR,
+    presentation_queue: Queue,
+    format: SurfaceFormatKHR,
+    extent: Extent2D,
+    images: Vec<Image>,
+    image_views: Vec<ImageView>,
+}
+
+impl<'a> ManagedSwapchain<'a> {
+    /// `old_swapchain` を渡すと、表示中のイメージを新しいスワップチェーンに引き継げる
+    ///
+    /// 引き継いだ後の `old_swapchain` には表示できないので、すぐに破棄してよい
+    pub fn new(
+        instance: &Instance,
+        physical_device: &PhysicalDevice,
+        device: &'a Device,
+        window: &ManagedWindow,
+        queue_indices: QueueFamilyIndices,
+        presentation_queue: Queue,
+        old_swapchain: Option<&ManagedSwapchain>,
+    ) -> anyhow::Result<ManagedSwapchain<'a>> {
+        let capabilities = window.get_surface_capabilities(physical_device)?;
+        let format = choose_surface_format(&window.get_surface_formats(physical_device)?)
+            .context("Surface has no formats")?;
+        let present_mode = choose_present_mode(&window.get_surface_present_modes(physical_device)?);
+        let extent = choose_extent(&capabilities, window.get_framebuffer_size());
+        debug!(
+            "Swapchain: {:?} {:?}, {:?}, {}x{}",
+            format.format, format.color_space, present_mode, extent.width, extent.height
+        );
+
+        // 最小枚数ちょうどだと、ドライバの処理を待つ間に次のイメージを取得できないことがある
+        let mut image_count = capabilities.min_image_count + 1;
+        if capabilities.max_image_count > 0 {
+            image_count = image_count.min(capabilities.max_image_count);
+        }
+
+        let queue_family_indices = queue_indices.unique();
+        let sharing_mode = if queue_family_indices.len() > 1 {
+            SharingMode::CONCURRENT
+        } else {
+            SharingMode::EXCLUSIVE
+        };
+        let create_info = SwapchainCreateInfoKHR::builder()
+            .surface(window.get_surface_raw())
+            .min_image_count(image_count)
+            .image_format(format.format)
+            .image_color_space(format.color_space)
+            .image_extent(extent)
+            .image_array_layers(1)
+            .image_usage(ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_sharing_mode(sharing_mode)
+            .queue_family_indices(&queue_family_indices)
+            .pre_transform(capabilities.current_transform)
+            .composite_alpha(CompositeAlphaFlagsKHR::OPAQUE)
+            .present_mode(present_mode)
+            .clipped(true)
+            .old_swapchain(old_swapchain.map_or(SwapchainKHR::null(), |old| old.swapchain_raw))
+            .build();
+        let swapchain_loader = Swapchain::new(instance, device);
+        let swapchain_raw = unsafe { swapchain_loader.create_swapchain(&create_info, None) }
+            .context("Failed to create swapchain")?;
+        // 途中で失敗した場合は、ここまでに作ったものを Drop で破棄する
+        let mut swapchain = ManagedSwapchain {
+            device,
+            swapchain_loader,
+            swapchain_raw,
+            presentation_queue,
+            format,
+            extent,
+            images: Vec::new(),
+            image_views: Vec::new(),
+        };
+        swapchain.images = unsafe {
+            swapchain
+                .swapchain_loader
+                .get_swapchain_images(swapchain_raw)
+        }
+        .context("Failed to get swapchain images")?;
+        for image in &swapchain.images {
+            let image_view = create_image_view(device, *image, format.format)?;
+            swapchain.image_views.push(image_view);
+        }
+        Ok(swapchain)
+    }
+
+    pub fn get_format(&self) -> Format {
+        self.format.format
+    }
+
+    pub fn get_extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    pub fn get_image_count(&self) -> usize {
+        self.images.len()
+    }
+
+    /// イメージごとのフレームバッファを作成する
+    pub fn create_framebuffers(
+        &'a self,
+        render_pass: &'a ManagedRenderPass,
+    ) -> anyhow::Result<Vec<ManagedFramebuffer<'a>>> {
+        self.image_views
+            .iter()
+            .map(|image_view| {
+                ManagedFramebuffer::new(
+                    self.device,
+                    render_pass,
+                    AttachmentView::new(self, *image_view),
+                    self.extent.width,
+                    self.extent.height,
+                )
+            })
+            .collect()
+    }
+
+    /// 次に描画するイメージのインデックスと、サーフェスに最適でなくなったかどうかを返す
+    ///
+    /// `semaphore` と `fence` は、イメージが使えるようになった時点でシグナルされる
+    pub fn acquire_next_image(
+        &self,
+        semaphore: Semaphore,
+        fence: Fence,
+    ) -> ash::prelude::VkResult<(u32, bool)> {
+        unsafe {
+            self.swapchain_loader
+                .acquire_next_image(self.swapchain_raw, u64::MAX, semaphore, fence)
+        }
+    }
+
+    /// `wait_semaphores` がシグナルされるのを待ってから、イメージを表示キューに送る
+    ///
+    /// サーフェスに最適でなくなった場合は `true` を返す
+    pub fn present(
+        &self,
+        image_index: u32,
+        wait_semaphores: &[Semaphore],
+    ) -> ash::prelude::VkResult<bool> {
+        let present_info = PresentInfoKHR::builder()
+            .wait_semaphores(wait_semaphores)
+            .swapchains(&[self.swapchain_raw])
+            .image_indices(&[image_index])
+            .build();
+        unsafe {
+            self.swapchain_loader
+                .queue_present(self.presentation_queue, &present_info)
+        }
+    }
+}
+
+impl Drop for ManagedSwapchain<'_> {
+    fn drop(&mut self) {
+        for image_view in self.image_views.iter() {
+            unsafe { self.device.destroy_image_view(*image_view, None) };
+        }
+        trace!("ImageViews of swapchain were destroyed");
+        unsafe {
+            self.swapchain_loader
+                .destroy_swapchain(self.swapchain_raw, None)
+        };
+        trace!("Swapchain was destroyed");
+    }
+}
+
+/// オフスクリーン描画と色が揃うよう、UNORM のフォーマットを優先する
+fn choose_surface_format(formats: &[SurfaceFormatKHR]) -> Option<SurfaceFormatKHR> {
+    formats
+        .iter()
+        .find(|format| {
+            format.format == Format::B8G8R8A8_UNORM
+                && format.color_space == ColorSpaceKHR::SRGB_NONLINEAR
+        })
+        .or_else(|| formats.first())
+        .copied()
+}
+
+/// 使えるなら MAILBOX を選び、そうでなければ必ずサポートされている FIFO にする
+fn choose_present_mode(present_modes: &[PresentModeKHR]) -> PresentModeKHR {
+    if present_modes.contains(&PresentModeKHR::MAILBOX) {
+        PresentModeKHR::MAILBOX
+    } else {
+        PresentModeKHR::FIFO
+    }
+}
+
+/// サーフェスがサイズを決めていない場合は、ウィンドウのフレームバッファのサイズに合わせる
+fn choose_extent(capabilities: &SurfaceCapabilitiesKHR, framebuffer_size: (u32, u32)) -> Extent2D {
+    if capabilities.current_extent.width != u32::MAX {
+        return capabilities.current_extent;
+    }
+    let (width, height) = framebuffer_size;
+    Extent2D {
+        width: width.clamp(
+            capabilities.min_image_extent.width,
+            capabilities.max_image_extent.width,
+        ),
+        height: height.clamp(
+            capabilities.min_image_extent.height,
+            capabilities.max_image_extent.height,
+        ),
+    }
+}
+
+fn create_image_view(device: &Device, image: Image, format: Format) -> anyhow::Result<ImageView> {
+    let create_info = ImageViewCreateInfo::builder()
+        .image(image)
+        .view_type(ImageViewType::TYPE_2D)
+        .format(format)
+        .components(
+            ComponentMapping::builder()
+                .r(ComponentSwizzle::IDENTITY)
+                .g(ComponentSwizzle::IDENTITY)
+                .b(ComponentSwizzle::IDENTITY)
+                .a(ComponentSwizzle::IDENTITY)
+                .build(),
+        )
+        .subresource_range(
+            ImageSubresourceRange::builder()
+                .aspect_mask(ImageAspectFlags::COLOR)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(0)
+                .layer_count(1)
+                .build(),
+        )
+        .build();
+    unsafe { device.create_image_view(&create_info, None) }
+        .context("Failed to create ImageView for swapchain image")
+}
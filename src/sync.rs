@@ -0,0 +1,80 @@
+use anyhow::Context;
+use ash::{
+    version::DeviceV1_0,
+    vk::{Fence, FenceCreateFlags, FenceCreateInfo, Semaphore, SemaphoreCreateInfo},
+    Device,
+};
+
+/// 自動で解放される、GPU 内の同期に使うセマフォのラッパー
+pub struct ManagedSemaphore<'a> {
+    device: &'a Device,
+    semaphore_raw: Semaphore,
+}
+
+impl<'a> ManagedSemaphore<'a> {
+    pub fn new(device: &'a Device) -> anyhow::Result<ManagedSemaphore<'a>> {
+        let create_info = SemaphoreCreateInfo::builder().build();
+        let semaphore_raw = unsafe { device.create_semaphore(&create_info, None) }
+            .context("Failed to create semaphore")?;
+        Ok(ManagedSemaphore {
+            device,
+            semaphore_raw,
+        })
+    }
+
+    pub fn get_semaphore_raw(&self) -> Semaphore {
+        self.semaphore_raw
+    }
+}
+
+impl Drop for ManagedSemaphore<'_> {
+    fn drop(&mut self) {
+        unsafe { self.device.destroy_semaphore(self.semaphore_raw, None) };
+        trace!("Semaphore was destroyed");
+    }
+}
+
+/// 自動で解放される、CPU と GPU の同期に使うフェンスのラッパー
+pub struct ManagedFence<'a> {
+    device: &'a Device,
+    fence_raw: Fence,
+}
+
+impl<'a> ManagedFence<'a> {
+    /// `signaled` が `true` なら、シグナル済みの状態で作成する
+    pub fn new(device: &'a Device, signaled: bool) -> anyhow::Result<ManagedFence<'a>> {
+        let flags = if signaled {
+            FenceCreateFlags::SIGNALED
+        } else {
+            FenceCreateFlags::empty()
+        };
+        let create_info = FenceCreateInfo::builder().flags(flags).build();
+        let fence_raw =
+            unsafe { device.create_fence(&create_info, None) }.context("Failed to create fence")?;
+        Ok(ManagedFence { device, fence_raw })
+    }
+
+    pub fn get_fence_raw(&self) -> Fence {
+        self.fence_raw
+    }
+
+    /// シグナルされるまで待つ
+    pub fn wait(&self) -> anyhow::Result<()> {
+        unsafe {
+            self.device
+                .wait_for_fences(&[self.fence_raw], true, u64::MAX)
+        }
+        .context("Failed to wait for fence")
+    }
+
+    pub fn reset(&self) -> anyhow::Result<()> {
+        unsafe { self.device.reset_fences(&[self.fence_raw]) }.context("Failed to reset fence")
+    }
+}
+
+impl Drop for ManagedFence<'_> {
+    fn drop(&mut self) {
+        unsafe { self.device.destroy_fence(self.fence_raw, None) };
+        trace!("Fence was destroyed");
+    }
+}
@@ -0,0 +1,241 @@
+//! シェーダから参照するテクスチャとサンプラ
+
+use crate::allocator::{Allocation, MemoryAllocator, ResourceKind};
+use anyhow::Context;
+use ash::{
+    version::DeviceV1_0,
+    vk::{
+        BorderColor, CompareOp, ComponentMapping, ComponentSwizzle, Extent3D, Filter, Format,
+        Image, ImageAspectFlags, ImageCreateInfo, ImageLayout, ImageSubresourceRange, ImageTiling,
+        ImageType, ImageUsageFlags, ImageView, ImageViewCreateInfo, ImageViewType,
+        MemoryPropertyFlags, SampleCountFlags, Sampler, SamplerAddressMode, SamplerCreateInfo,
+        SamplerMipmapMode, SharingMode,
+    },
+    Device,
+};
+
+/// サンプラの設定
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerOptions {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    pub address_mode: SamplerAddressMode,
+    /// 異方性フィルタリングの最大値 (`None` なら使わない)
+    ///
+    /// デバイスが対応していない場合は無視され、上限を超える値は上限に丸められる
+    pub max_anisotropy: Option<f32>,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        SamplerOptions {
+            mag_filter: Filter::LINEAR,
+            min_filter: Filter::LINEAR,
+            address_mode: SamplerAddressMode::REPEAT,
+            max_anisotropy: None,
+        }
+    }
+}
+
+impl SamplerOptions {
+    /// ドット絵向けの、補間しないサンプラ
+    pub fn nearest() -> SamplerOptions {
+        SamplerOptions {
+            mag_filter: Filter::NEAREST,
+            min_filter: Filter::NEAREST,
+            ..SamplerOptions::default()
+        }
+    }
+}
+
+/// 自動で解放される、サンプラのラッパー
+pub struct ManagedSampler<'a> {
+    device: &'a Device,
+    sampler_raw: Sampler,
+}
+
+impl<'a> ManagedSampler<'a> {
+    /// `max_supported_anisotropy` は、異方性フィルタリングが無効なデバイスでは `None`
+    pub fn new(
+        device: &'a Device,
+        options: SamplerOptions,
+        max_supported_anisotropy: Option<f32>,
+    ) -> anyhow::Result<ManagedSampler<'a>> {
+        let max_anisotropy = match (options.max_anisotropy, max_supported_anisotropy) {
+            (Some(requested), Some(supported)) => Some(requested.min(supported).max(1.0)),
+            (Some(_), None) => {
+                warn!("Anisotropic filtering is not enabled on this device");
+                None
+            }
+            (None, _) => None,
+        };
+        let create_info = SamplerCreateInfo::builder()
+            .mag_filter(options.mag_filter)
+            .min_filter(options.min_filter)
+            .address_mode_u(options.address_mode)
+            .address_mode_v(options.address_mode)
+            .address_mode_w(options.address_mode)
+            .anisotropy_enable(max_anisotropy.is_some())
+            .max_anisotropy(max_anisotropy.unwrap_or(1.0))
+            .border_color(BorderColor::INT_OPAQUE_BLACK)
+            .unnormalized_coordinates(false)
+            .compare_enable(false)
+            .compare_op(CompareOp::ALWAYS)
+            .mipmap_mode(SamplerMipmapMode::LINEAR)
+            .mip_lod_bias(0.0)
+            .min_lod(0.0)
+            .max_lod(0.0)
+            .build();
+        let sampler_raw = unsafe { device.create_sampler(&create_info, None) }
+            .context("Failed to create sampler")?;
+        Ok(ManagedSampler {
+            device,
+            sampler_raw,
+        })
+    }
+
+    pub fn get_sampler_raw(&self) -> Sampler {
+        self.sampler_raw
+    }
+}
+
+impl Drop for ManagedSampler<'_> {
+    fn drop(&mut self) {
+        unsafe { self.device.destroy_sampler(self.sampler_raw, None) };
+        trace!("Sampler was destroyed");
+    }
+}
+
+/// 自動で解放される、GPU 専用のメモリに置いたテクスチャとそのサンプラ
+///
+/// `ManagedLogicalDevice::create_texture` で作ったものは `SHADER_READ_ONLY_OPTIMAL` レイアウトになっている
+pub struct ManagedTexture<'a> {
+    device: &'a Device,
+    /// イメージより後に解放されるように、フィールドとして持っておく
+    _allocation: Allocation<'a>,
+    image_raw: Image,
+    image_view: ImageView,
+    sampler: ManagedSampler<'a>,
+    width: u32,
+    height: u32,
+}
+
+impl<'a> ManagedTexture<'a> {
+    /// 転送先になる空のイメージを作る (中身は `ManagedLogicalDevice::create_texture` で転送する)
+    pub fn new(
+        device: &'a Device,
+        allocator: &'a MemoryAllocator,
+        sampler: ManagedSampler<'a>,
+        width: u32,
+        height: u32,
+        format: Format,
+    ) -> anyhow::Result<ManagedTexture<'a>> {
+        ensure!(
+            width > 0 && height > 0,
+            "Texture size must not be zero ({}x{})",
+            width,
+            height
+        );
+        let create_info = ImageCreateInfo::builder()
+            .image_type(ImageType::TYPE_2D)
+            .extent(Extent3D {
+                width,
+                height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .format(format)
+            .tiling(ImageTiling::OPTIMAL)
+            .initial_layout(ImageLayout::UNDEFINED)
+            .usage(
+                ImageUsageFlags::SAMPLED
+                    | ImageUsageFlags::TRANSFER_DST
+                    | ImageUsageFlags::TRANSFER_SRC,
+            )
+            .sharing_mode(SharingMode::EXCLUSIVE)
+            .samples(SampleCountFlags::TYPE_1)
+            .build();
+        let image_raw = unsafe { device.create_image(&create_info, None) }
+            .context("Failed to create texture image")?;
+        let allocation = match allocator.allocate_for_image(
+            image_raw,
+            ResourceKind::Optimal,
+            MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryPropertyFlags::empty(),
+        ) {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                unsafe { device.destroy_image(image_raw, None) };
+                return Err(err).context("Failed to allocate memory for texture");
+            }
+        };
+        let image_view_create_info = ImageViewCreateInfo::builder()
+            .image(image_raw)
+            .view_type(ImageViewType::TYPE_2D)
+            .format(format)
+            .components(
+                ComponentMapping::builder()
+                    .r(ComponentSwizzle::IDENTITY)
+                    .g(ComponentSwizzle::IDENTITY)
+                    .b(ComponentSwizzle::IDENTITY)
+                    .a(ComponentSwizzle::IDENTITY)
+                    .build(),
+            )
+            .subresource_range(
+                ImageSubresourceRange::builder()
+                    .aspect_mask(ImageAspectFlags::COLOR)
+                    .base_mip_level(0)
+                    .level_count(1)
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .build(),
+            )
+            .build();
+        let image_view = match unsafe { device.create_image_view(&image_view_create_info, None) } {
+            Ok(image_view) => image_view,
+            Err(err) => {
+                unsafe { device.destroy_image(image_raw, None) };
+                return Err(err).context("Failed to create ImageView for texture");
+            }
+        };
+        Ok(ManagedTexture {
+            device,
+            _allocation: allocation,
+            image_raw,
+            image_view,
+            sampler,
+            width,
+            height,
+        })
+    }
+
+    pub fn get_image_raw(&self) -> Image {
+        self.image_raw
+    }
+
+    pub fn get_image_view_raw(&self) -> ImageView {
+        self.image_view
+    }
+
+    pub fn get_sampler(&self) -> &ManagedSampler<'a> {
+        &self.sampler
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+}
+
+impl Drop for ManagedTexture<'_> {
+    fn drop(&mut self) {
+        unsafe { self.device.destroy_image_view(self.image_view, None) };
+        trace!("ImageView of texture was destroyed");
+        unsafe { self.device.destroy_image(self.image_raw, None) };
+        trace!("Texture image was destroyed");
+    }
+}
@@ -0,0 +1,136 @@
+//! 頂点バッファのレイアウト
+
+#[doc(hidden)]
+pub use ash::vk::VertexInputAttributeDescription;
+use ash::vk::{Format, VertexInputBindingDescription, VertexInputRate};
+use std::mem::size_of;
+
+/// 頂点バッファに並べる 1 頂点分のデータ
+///
+/// 通常は `impl_vertex!` で実装する。フィールドの並びを保証するため `#[repr(C)]` を付けること
+pub trait Vertex: Copy {
+    fn binding_description(binding: u32) -> VertexInputBindingDescription {
+        VertexInputBindingDescription::builder()
+            .binding(binding)
+            .stride(size_of::<Self>() as u32)
+            .input_rate(VertexInputRate::VERTEX)
+            .build()
+    }
+
+    /// シェーダの `location` は、フィールドの順に 0 から割り当てる
+    fn attribute_descriptions(binding: u32) -> Vec<VertexInputAttributeDescription>;
+}
+
+/// 頂点属性として使える型と、対応するフォーマット
+pub trait VertexAttribute {
+    const FORMAT: Format;
+}
+
+macro_rules! vertex_attributes {
+    ($($ty:ty => $format:ident),* $(,)?) => {
+        $(
+            impl VertexAttribute for $ty {
+                const FORMAT: Format = Format::$format;
+            }
+        )*
+    };
+}
+
+vertex_attributes! {
+    f32 => R32_SFLOAT,
+    [f32; 2] => R32G32_SFLOAT,
+    [f32; 3] => R32G32B32_SFLOAT,
+    [f32; 4] => R32G32B32A32_SFLOAT,
+    u32 => R32_UINT,
+    [u32; 2] => R32G32_UINT,
+    [u32; 3] => R32G32B32_UINT,
+    [u32; 4] => R32G32B32A32_UINT,
+    i32 => R32_SINT,
+    [i32; 2] => R32G32_SINT,
+    [i32; 3] => R32G32B32_SINT,
+    [i32; 4] => R32G32B32A32_SINT,
+    [u8; 4] => R8G8B8A8_UNORM,
+}
+
+/// `impl_vertex!` から使う。ポインタの指す型から頂点属性のフォーマットを決める
+#[doc(hidden)]
+pub fn format_of<T>(_field: *const T) -> Format
+where
+    T: VertexAttribute,
+{
+    T::FORMAT
+}
+
+/// 構造体のフィールドから `Vertex` を実装する
+///
+/// ```ignore
+/// #[repr(C)]
+/// #[derive(Clone, Copy)]
+/// struct MyVertex {
+///     position: [f32; 3],
+///     color: [f32; 3],
+/// }
+///
+/// impl_vertex!(MyVertex { position, color });
+/// ```
+#[macro_export]
+macro_rules! impl_vertex {
+    ($vertex:ty { $($field:ident),+ $(,)? }) => {
+        impl $crate::vertex::Vertex for $vertex {
+            fn attribute_descriptions(
+                binding: u32,
+            ) -> Vec<$crate::vertex::VertexInputAttributeDescription> {
+                let uninit = std::mem::MaybeUninit::<$vertex>::uninit();
+                let base = uninit.as_ptr();
+                let fields = [$(
+                    // 未初期化の値は読まずに、フィールドのアドレスだけを使う
+                    unsafe {
+                        let field = std::ptr::addr_of!((*base).$field);
+                        (
+                            $crate::vertex::format_of(field),
+                            (field as *const u8).offset_from(base as *const u8) as u32,
+                        )
+                    }
+                ),+];
+                fields
+                    .iter()
+                    .enumerate()
+                    .map(|(location, (format, offset))| {
+                        $crate::vertex::VertexInputAttributeDescription::builder()
+                            .binding(binding)
+                            .location(location as u32)
+                            .format(*format)
+                            .offset(*offset)
+                            .build()
+                    })
+                    .collect()
+            }
+        }
+    };
+}
+
+/// 位置と色だけを持つ 2D の頂点
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorVertex2D {
+    pub position: [f32; 2],
+    pub color: [f32; 3],
+}
+
+impl_vertex!(ColorVertex2D { position, color });
+
+/// 画面の中央に描く三角形
+pub const TRIANGLE_VERTICES: [ColorVertex2D; 3] = [
+    ColorVertex2D {
+        position: [0.0, -0.5],
+        color: [1.0, 0.0, 0.0],
+    },
+    ColorVertex2D {
+        position: [0.5, 0.5],
+        color: [0.0, 1.0, 0.0],
+    },
+    ColorVertex2D {
+        position: [-0.5, 0.5],
+        color: [0.0, 0.0, 1.0],
+    },
+];
@@ -1,27 +1,97 @@
+use crate::input::Event;
+use anyhow::Context;
 use ash::{
     extensions::khr::Surface,
-    vk::{PhysicalDevice, SurfaceKHR},
+    vk::{PhysicalDevice, PresentModeKHR, SurfaceCapabilitiesKHR, SurfaceFormatKHR, SurfaceKHR},
 };
-use glfw::Window;
+use glfw::{Window, WindowEvent};
+use std::sync::mpsc::Receiver;
 
 /// 自動で解放される、GLFW ウィンドウとそのサーフェスのラッパー
 pub struct ManagedWindow {
-    _window_raw: Window,
+    window_raw: Window,
+    event_receiver: Receiver<(f64, WindowEvent)>,
     surface_loader: Surface,
     surface: SurfaceKHR,
 }
 
 impl ManagedWindow {
-    pub fn new(mut window_raw: Window, surface_loader: Surface, surface: SurfaceKHR) -> Self {
+    pub fn new(
+        mut window_raw: Window,
+        event_receiver: Receiver<(f64, WindowEvent)>,
+        surface_loader: Surface,
+        surface: SurfaceKHR,
+    ) -> Self {
         window_raw.set_key_polling(true);
+        window_raw.set_mouse_button_polling(true);
+        window_raw.set_cursor_pos_polling(true);
+        window_raw.set_scroll_polling(true);
+        window_raw.set_focus_polling(true);
+        window_raw.set_close_polling(true);
+        window_raw.set_framebuffer_size_polling(true);
 
         ManagedWindow {
-            _window_raw: window_raw,
+            window_raw,
+            event_receiver,
             surface_loader,
             surface,
         }
     }
 
+    /// 前回の呼び出し以降に届いたイベントを取り出す
+    ///
+    /// 事前に `GlfwWrapper::poll_events` でイベントを処理しておく必要がある
+    pub fn flush_events(&self) -> impl Iterator<Item = Event> + '_ {
+        glfw::flush_messages(&self.event_receiver).filter_map(|(_, event)| Event::from_glfw(event))
+    }
+
+    pub fn should_close(&self) -> bool {
+        self.window_raw.should_close()
+    }
+
+    pub fn get_surface_raw(&self) -> SurfaceKHR {
+        self.surface
+    }
+
+    /// フレームバッファのサイズ (ピクセル単位)
+    pub fn get_framebuffer_size(&self) -> (u32, u32) {
+        let (width, height) = self.window_raw.get_framebuffer_size();
+        (width.max(0) as u32, height.max(0) as u32)
+    }
+
+    pub fn get_surface_capabilities(
+        &self,
+        physical_device: &PhysicalDevice,
+    ) -> anyhow::Result<SurfaceCapabilitiesKHR> {
+        unsafe {
+            self.surface_loader
+                .get_physical_device_surface_capabilities(*physical_device, self.surface)
+        }
+        .context("Failed to get surface capabilities")
+    }
+
+    pub fn get_surface_formats(
+        &self,
+        physical_device: &PhysicalDevice,
+    ) -> anyhow::Result<Vec<SurfaceFormatKHR>> {
+        unsafe {
+            self.surface_loader
+                .get_physical_device_surface_formats(*physical_device, self.surface)
+        }
+        .context("Failed to get surface formats")
+    }
+
+    pub fn get_surface_present_modes(
+        &self,
+        physical_device: &PhysicalDevice,
+    ) -> anyhow::Result<Vec<PresentModeKHR>> {
+        unsafe {
+            self.surface_loader
+                .get_physical_device_surface_present_modes(*physical_device, self.surface)
+        }
+        .context("Failed to get surface present modes")
+    }
+
     pub fn get_physical_device_surface_support(
         &self,
         physical_device: &PhysicalDevice,
@@ -0,0 +1,71 @@
+use game::allocator::{ResourceKind, SubAllocator};
+
+#[test]
+fn allocations_respect_alignment() {
+    let mut allocator = SubAllocator::new(1024, 1);
+    assert_eq!(allocator.allocate(10, 4, ResourceKind::Linear), Some(0));
+    assert_eq!(allocator.allocate(10, 256, ResourceKind::Linear), Some(256));
+    assert_eq!(allocator.allocate(10, 4, ResourceKind::Linear), Some(12));
+}
+
+#[test]
+fn different_kinds_do_not_share_a_granularity_page() {
+    let mut allocator = SubAllocator::new(4096, 1024);
+    assert_eq!(allocator.allocate(100, 4, ResourceKind::Linear), Some(0));
+    assert_eq!(
+        allocator.allocate(100, 4, ResourceKind::Optimal),
+        Some(1024)
+    );
+    // 同じ種類なら詰めて置ける
+    assert_eq!(allocator.allocate(100, 4, ResourceKind::Linear), Some(100));
+    // 隙間に収まり、後ろの Optimal とは別のページで終わるので、隙間に置ける
+    assert_eq!(allocator.allocate(800, 4, ResourceKind::Linear), Some(200));
+}
+
+#[test]
+fn linear_before_optimal_does_not_share_its_page() {
+    let mut allocator = SubAllocator::new(4096, 1024);
+    let first = allocator.allocate(100, 4, ResourceKind::Optimal).unwrap();
+    assert_eq!(allocator.allocate(100, 4, ResourceKind::Optimal), Some(100));
+    allocator.free(first);
+    // 先頭の隙間には収まるが、後ろの Optimal と同じページに入ってしまうので使わない
+    assert_eq!(allocator.allocate(50, 4, ResourceKind::Linear), Some(1024));
+}
+
+#[test]
+fn optimal_before_linear_does_not_share_its_page() {
+    let mut allocator = SubAllocator::new(4096, 1024);
+    let first = allocator.allocate(100, 4, ResourceKind::Linear).unwrap();
+    assert_eq!(allocator.allocate(100, 4, ResourceKind::Linear), Some(100));
+    allocator.free(first);
+    assert_eq!(allocator.allocate(50, 4, ResourceKind::Optimal), Some(1024));
+}
+
+#[test]
+fn freed_ranges_are_reused() {
+    let mut allocator = SubAllocator::new(300, 1);
+    let first = allocator.allocate(100, 1, ResourceKind::Linear).unwrap();
+    let second = allocator.allocate(100, 1, ResourceKind::Linear).unwrap();
+    allocator.allocate(100, 1, ResourceKind::Linear).unwrap();
+    assert_eq!(allocator.allocate(1, 1, ResourceKind::Linear), None);
+
+    allocator.free(second);
+    assert_eq!(allocator.used_size(), 200);
+    assert_eq!(
+        allocator.allocate(100, 1, ResourceKind::Linear),
+        Some(second)
+    );
+
+    allocator.free(first);
+    allocator.free(second);
+    allocator.free(200);
+    assert!(allocator.is_empty());
+}
+
+#[test]
+fn allocation_fails_when_it_does_not_fit() {
+    let mut allocator = SubAllocator::new(256, 1);
+    assert_eq!(allocator.allocate(512, 1, ResourceKind::Linear), None);
+    assert_eq!(allocator.allocate(200, 128, ResourceKind::Linear), Some(0));
+    assert_eq!(allocator.allocate(50, 128, ResourceKind::Linear), None);
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use ash::vk::BufferUsageFlags;
+use common::with_headless_instance;
+use game::buffer::MemoryLocation;
+
+#[test]
+fn staging_upload_round_trip() {
+    let data: Vec<u32> = (0..1024).map(|i| i * 3 + 1).collect();
+    let read_back = with_headless_instance(|instance| {
+        let logical_device = instance.create_logical_device(None)?;
+        let command_pool = logical_device.create_command_pool()?;
+        let buffer = logical_device.create_device_local_buffer(
+            &command_pool,
+            BufferUsageFlags::TRANSFER_SRC,
+            &data,
+        )?;
+        let read_back_buffer = logical_device.create_buffer(
+            buffer.get_size(),
+            BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+        )?;
+        command_pool.allocate_command_buffer()?.copy_buffer(
+            &logical_device.get_graphics_queue(),
+            &buffer,
+            &read_back_buffer,
+            buffer.get_size(),
+        )?;
+        read_back_buffer.read::<u32>(data.len())
+    })
+    .expect("Failed to upload buffer");
+    assert_eq!(read_back, data);
+}
+
+#[test]
+fn write_rejects_data_larger_than_buffer() {
+    with_headless_instance(|instance| {
+        let logical_device = instance.create_logical_device(None)?;
+        let buffer = logical_device.create_buffer(
+            16,
+            BufferUsageFlags::UNIFORM_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )?;
+        assert!(buffer.write(&[0u32; 4]).is_ok());
+        assert!(buffer.write(&[0u32; 5]).is_err());
+        Ok(())
+    })
+    .expect("Failed to create buffer");
+}
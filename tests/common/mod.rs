@@ -0,0 +1,117 @@
+//! 描画結果を参照画像と比較するゴールデンイメージテスト用のヘルパ
+//!
+//! 環境変数 `UPDATE_GOLDEN=1` を付けて実行すると、比較せずに参照画像を書き換える
+
+// テストごとに使うヘルパが違うので、使われていないものがあっても警告しない
+#![allow(dead_code)]
+
+use anyhow::Context;
+use ash::Entry;
+use game::instance::ManagedInstance;
+use image::{Rgba, RgbaImage};
+use std::path::PathBuf;
+
+/// 参照画像との差をどこまで許容するか
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    /// 1 チャンネルあたりの差の許容値
+    pub channel: u8,
+    /// 許容値を超えたピクセルがいくつまでならテストを通すか (ポリゴンの縁の差を吸収する)
+    pub max_mismatched_pixels: usize,
+}
+
+/// ヘッドレスのインスタンスを用意して描画処理を実行する
+pub fn with_headless_instance<F, T>(f: F) -> anyhow::Result<T>
+where
+    F: FnOnce(&ManagedInstance) -> anyhow::Result<T>,
+{
+    let entry = unsafe { Entry::new() }?;
+    let mut instance = ManagedInstance::new(&entry, None, cfg!(feature = "validation_layers"))?;
+    // テストでユーザのパイプラインキャッシュを書き換えない
+    instance.set_pipeline_cache_path(None);
+    // バリデーションエラーが出たらテストを失敗させる
+    instance.set_panic_on_validation_error(true);
+    f(&instance)
+}
+
+/// `tests/golden/<name>.png` と比較し、一致しなければ `target/golden/` に実際の画像と差分画像を書き出して失敗する
+pub fn assert_matches_golden(name: &str, actual: &RgbaImage, tolerance: Tolerance) {
+    if let Err(err) = check_golden(name, actual, tolerance) {
+        panic!("Golden image test `{}` failed: {:?}", name, err);
+    }
+}
+
+fn check_golden(name: &str, actual: &RgbaImage, tolerance: Tolerance) -> anyhow::Result<()> {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let reference_path = manifest_dir
+        .join("tests/golden")
+        .join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        actual
+            .save(&reference_path)
+            .with_context(|| format!("Failed to save {}", reference_path.display()))?;
+        eprintln!("Updated {}", reference_path.display());
+        return Ok(());
+    }
+    let expected = image::open(&reference_path)
+        .with_context(|| {
+            format!(
+                "Failed to open {} (run with UPDATE_GOLDEN=1 to create it)",
+                reference_path.display()
+            )
+        })?
+        .to_rgba8();
+    let (diff, mismatched_pixels) = compare(&expected, actual, tolerance.channel)?;
+    if mismatched_pixels <= tolerance.max_mismatched_pixels {
+        return Ok(());
+    }
+    let output_dir = manifest_dir.join("target/golden");
+    std::fs::create_dir_all(&output_dir)
+        .with_context(|| format!("Failed to create {}", output_dir.display()))?;
+    let actual_path = output_dir.join(format!("{}.actual.png", name));
+    let diff_path = output_dir.join(format!("{}.diff.png", name));
+    actual.save(&actual_path)?;
+    diff.save(&diff_path)?;
+    anyhow::bail!(
+        "{} pixels differ by more than {} (allowed: {})\n  actual: {}\n  diff:   {}",
+        mismatched_pixels,
+        tolerance.channel,
+        tolerance.max_mismatched_pixels,
+        actual_path.display(),
+        diff_path.display()
+    )
+}
+
+/// 2 つの画像を比較し、差分画像と許容値を超えたピクセル数を返す
+///
+/// 差分画像では、許容値を超えたピクセルを赤で、それ以外を参照画像の暗い灰色で表す
+pub fn compare(
+    expected: &RgbaImage,
+    actual: &RgbaImage,
+    channel_tolerance: u8,
+) -> anyhow::Result<(RgbaImage, usize)> {
+    anyhow::ensure!(
+        expected.dimensions() == actual.dimensions(),
+        "Image size mismatch (expected: {:?}, actual: {:?})",
+        expected.dimensions(),
+        actual.dimensions()
+    );
+    let mut mismatched_pixels = 0;
+    let diff = RgbaImage::from_fn(expected.width(), expected.height(), |x, y| {
+        let expected = expected.get_pixel(x, y);
+        let actual = actual.get_pixel(x, y);
+        let exceeds = expected
+            .0
+            .iter()
+            .zip(actual.0.iter())
+            .any(|(e, a)| (*e as i16 - *a as i16).abs() > channel_tolerance as i16);
+        if exceeds {
+            mismatched_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let luma = (expected[0] as u16 + expected[1] as u16 + expected[2] as u16) / 3 / 4;
+            Rgba([luma as u8, luma as u8, luma as u8, 255])
+        }
+    });
+    Ok((diff, mismatched_pixels))
+}
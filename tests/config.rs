@@ -0,0 +1,31 @@
+use game::{config::Config, physical_device::DeviceSelection};
+
+#[test]
+fn device_is_read_from_config() {
+    let config = Config::from_toml("device = \"1\"").unwrap();
+    assert_eq!(config.device_selection(), DeviceSelection::Index(1));
+    let config = Config::from_toml("device = \"GeForce\"").unwrap();
+    assert_eq!(
+        config.device_selection(),
+        DeviceSelection::Name("GeForce".to_owned())
+    );
+}
+
+#[test]
+fn empty_config_selects_automatically() {
+    let config = Config::from_toml("").unwrap();
+    assert_eq!(config, Config::default());
+    assert_eq!(config.device_selection(), DeviceSelection::Auto);
+}
+
+#[test]
+fn unknown_keys_are_rejected() {
+    assert!(Config::from_toml("devise = \"1\"").is_err());
+}
+
+#[test]
+fn missing_file_uses_defaults() {
+    let path =
+        std::env::temp_dir().join(format!("game-missing-config-{}.toml", std::process::id()));
+    assert_eq!(Config::load(&path).unwrap(), Config::default());
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use ash::vk::{BufferUsageFlags, ShaderStageFlags};
+use common::with_headless_instance;
+use game::{
+    buffer::MemoryLocation,
+    descriptor::{DescriptorBinding, DescriptorWriter},
+    texture::SamplerOptions,
+};
+use image::RgbaImage;
+
+#[test]
+fn descriptor_pool_grows_when_full() {
+    let pool_count = with_headless_instance(|instance| {
+        let logical_device = instance.create_logical_device(None)?;
+        let layout = logical_device.create_descriptor_set_layout(&[
+            DescriptorBinding::uniform_buffer(0, ShaderStageFlags::VERTEX),
+            DescriptorBinding::combined_image_sampler(1, ShaderStageFlags::FRAGMENT),
+        ])?;
+        let mut pool = logical_device.create_descriptor_pool();
+        let mut sets = Vec::new();
+        for _ in 0..100 {
+            sets.push(pool.allocate(&layout)?);
+        }
+        sets.extend(pool.allocate_per_frame(&layout, 3)?);
+        assert_eq!(sets.len(), 103);
+        let pool_count = pool.get_pool_count();
+        pool.reset()?;
+        assert_eq!(pool.get_pool_count(), 1);
+        pool.allocate(&layout)?;
+        Ok(pool_count)
+    })
+    .expect("Failed to allocate descriptor sets");
+    assert!(pool_count > 1);
+}
+
+#[test]
+fn write_uniform_buffer_and_texture() {
+    with_headless_instance(|instance| {
+        let logical_device = instance.create_logical_device(None)?;
+        let command_pool = logical_device.create_command_pool()?;
+        let layout = logical_device.create_descriptor_set_layout(&[
+            DescriptorBinding::uniform_buffer(0, ShaderStageFlags::VERTEX),
+            DescriptorBinding::combined_image_sampler(1, ShaderStageFlags::FRAGMENT),
+        ])?;
+        let mut pool = logical_device.create_descriptor_pool();
+        let sets = pool.allocate_per_frame(&layout, 2)?;
+        let uniform_buffers = (0..sets.len())
+            .map(|_| {
+                logical_device.create_buffer(
+                    64,
+                    BufferUsageFlags::UNIFORM_BUFFER,
+                    MemoryLocation::CpuToGpu,
+                )
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let texture = logical_device.create_texture(
+            &command_pool,
+            &RgbaImage::new(4, 4),
+            SamplerOptions::default(),
+        )?;
+        let mut writer = DescriptorWriter::new();
+        for (set, buffer) in sets.iter().zip(uniform_buffers.iter()) {
+            writer
+                .write_uniform_buffer(*set, 0, buffer)
+                .write_texture(*set, 1, &texture);
+        }
+        assert_eq!(writer.len(), 4);
+        logical_device.update_descriptor_sets(&writer);
+        Ok(())
+    })
+    .expect("Failed to write descriptor sets");
+}
+
+#[test]
+fn duplicate_bindings_are_rejected() {
+    with_headless_instance(|instance| {
+        let logical_device = instance.create_logical_device(None)?;
+        let result = logical_device.create_descriptor_set_layout(&[
+            DescriptorBinding::uniform_buffer(0, ShaderStageFlags::VERTEX),
+            DescriptorBinding::uniform_buffer(0, ShaderStageFlags::FRAGMENT),
+        ]);
+        assert!(result.is_err());
+        Ok(())
+    })
+    .expect("Failed to create logical device");
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use common::{assert_matches_golden, compare, with_headless_instance, Tolerance};
+use game::headless;
+use image::{Rgba, RgbaImage};
+
+const TOLERANCE: Tolerance = Tolerance {
+    channel: 3,
+    max_mismatched_pixels: 64,
+};
+
+#[test]
+fn triangle() {
+    let image = with_headless_instance(|instance| headless::render_triangle(instance, 160, 120))
+        .expect("Failed to render triangle");
+    assert_matches_golden("triangle", &image, TOLERANCE);
+}
+
+#[test]
+fn compare_counts_pixels_beyond_tolerance() {
+    let expected = RgbaImage::from_pixel(4, 4, Rgba([100, 100, 100, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(0, 0, Rgba([103, 100, 100, 255]));
+    actual.put_pixel(1, 0, Rgba([104, 100, 100, 255]));
+    let (diff, mismatched_pixels) = compare(&expected, &actual, 3).unwrap();
+    assert_eq!(mismatched_pixels, 1);
+    assert_eq!(*diff.get_pixel(1, 0), Rgba([255, 0, 0, 255]));
+    assert_ne!(*diff.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+}
+
+#[test]
+fn compare_rejects_size_mismatch() {
+    let expected = RgbaImage::new(4, 4);
+    let actual = RgbaImage::new(4, 3);
+    assert!(compare(&expected, &actual, 0).is_err());
+}
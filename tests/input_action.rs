@@ -0,0 +1,187 @@
+use game::input::{
+    action::{ActionMap, Binding, Bindings},
+    gamepad::Deadzones,
+    ButtonAction, Event, GamepadAxis, GamepadButton, JoystickId, Key, MouseButton,
+};
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+enum Action {
+    Jump,
+    MoveX,
+    MoveY,
+}
+
+const BINDINGS: &str = r#"
+[buttons]
+Jump = ["Space", "Mouse1", "GamepadA"]
+
+[axes]
+MoveX = [
+    { negative = "A", positive = "D" },
+    { negative = "Left", positive = "Right" },
+    { gamepad = "LeftX" },
+]
+MoveY = [{ gamepad = "LeftY", invert = true }]
+"#;
+
+fn key(key: Key, action: ButtonAction) -> Event {
+    Event::Key { key, action }
+}
+
+fn action_map() -> ActionMap<Action> {
+    ActionMap::new(Bindings::from_toml(BINDINGS).unwrap())
+}
+
+#[test]
+fn parse_bindings() {
+    let bindings = Bindings::<Action>::from_toml(BINDINGS).unwrap();
+    assert_eq!(
+        bindings.buttons[&Action::Jump],
+        vec![
+            Binding::Key(Key::Space),
+            Binding::MouseButton(MouseButton::Button1),
+            Binding::GamepadButton(GamepadButton::ButtonA),
+        ]
+    );
+    assert_eq!(bindings.axes[&Action::MoveX].len(), 3);
+}
+
+#[test]
+fn reject_unknown_key_name() {
+    let result = Bindings::<Action>::from_toml("[buttons]\nJump = [\"NoSuchKey\"]\n");
+    assert!(result.is_err());
+}
+
+#[test]
+fn just_pressed_lasts_one_frame() {
+    let mut actions = action_map();
+    actions.begin_frame();
+    actions.handle_event(&key(Key::Space, ButtonAction::Press));
+    assert!(actions.is_pressed(Action::Jump));
+    assert!(actions.is_just_pressed(Action::Jump));
+
+    actions.begin_frame();
+    actions.handle_event(&key(Key::Space, ButtonAction::Repeat));
+    assert!(actions.is_pressed(Action::Jump));
+    assert!(!actions.is_just_pressed(Action::Jump));
+
+    actions.begin_frame();
+    actions.handle_event(&key(Key::Space, ButtonAction::Release));
+    assert!(!actions.is_pressed(Action::Jump));
+    assert!(actions.is_just_released(Action::Jump));
+}
+
+#[test]
+fn released_only_when_every_binding_is_up() {
+    let mut actions = action_map();
+    actions.handle_event(&key(Key::Space, ButtonAction::Press));
+    actions.handle_event(&Event::MouseButton {
+        button: MouseButton::Button1,
+        action: ButtonAction::Press,
+    });
+    actions.begin_frame();
+    actions.handle_event(&key(Key::Space, ButtonAction::Release));
+    assert!(actions.is_pressed(Action::Jump));
+    assert!(!actions.is_just_released(Action::Jump));
+}
+
+#[test]
+fn axis_combines_bindings() {
+    let mut actions = action_map();
+    assert_eq!(actions.axis(Action::MoveX), 0.0);
+    actions.handle_event(&key(Key::D, ButtonAction::Press));
+    assert_eq!(actions.axis(Action::MoveX), 1.0);
+    actions.handle_event(&key(Key::Right, ButtonAction::Press));
+    assert_eq!(actions.axis(Action::MoveX), 1.0);
+    actions.handle_event(&key(Key::A, ButtonAction::Press));
+    actions.handle_event(&key(Key::Left, ButtonAction::Press));
+    assert_eq!(actions.axis(Action::MoveX), 0.0);
+}
+
+#[test]
+fn losing_focus_releases_keyboard_and_mouse() {
+    let mut actions = action_map();
+    actions.handle_event(&key(Key::Space, ButtonAction::Press));
+    actions.begin_frame();
+    actions.handle_event(&Event::Focused(false));
+    assert!(!actions.is_pressed(Action::Jump));
+    assert!(actions.is_just_released(Action::Jump));
+}
+
+#[test]
+fn reject_unknown_action() {
+    let result = Bindings::<Action>::from_toml("[buttons]\nFly = [\"Space\"]\n");
+    assert!(result.is_err());
+}
+
+fn gamepad_axis(id: JoystickId, axis: GamepadAxis, value: f32) -> Event {
+    Event::GamepadAxis { id, axis, value }
+}
+
+#[test]
+fn gamepad_button_triggers_action() {
+    let mut actions = action_map();
+    actions.handle_event(&Event::GamepadButton {
+        id: JoystickId::Joystick1,
+        button: GamepadButton::ButtonA,
+        action: ButtonAction::Press,
+    });
+    assert!(actions.is_just_pressed(Action::Jump));
+    // キーボードのフォーカスが外れてもゲームパッドの入力は残る
+    actions.handle_event(&Event::Focused(false));
+    assert!(actions.is_pressed(Action::Jump));
+}
+
+#[test]
+fn gamepad_axis_uses_largest_deflection() {
+    let mut actions = action_map();
+    actions.handle_event(&gamepad_axis(
+        JoystickId::Joystick1,
+        GamepadAxis::AxisLeftX,
+        0.25,
+    ));
+    actions.handle_event(&gamepad_axis(
+        JoystickId::Joystick2,
+        GamepadAxis::AxisLeftX,
+        -0.5,
+    ));
+    assert_eq!(actions.axis(Action::MoveX), -0.5);
+    actions.handle_event(&Event::GamepadDisconnected(JoystickId::Joystick2));
+    assert_eq!(actions.axis(Action::MoveX), 0.25);
+}
+
+#[test]
+fn inverted_gamepad_axis() {
+    let mut actions = action_map();
+    actions.handle_event(&gamepad_axis(
+        JoystickId::Joystick1,
+        GamepadAxis::AxisLeftY,
+        0.75,
+    ));
+    assert_eq!(actions.axis(Action::MoveY), -0.75);
+}
+
+#[test]
+fn stick_deadzone_is_radial() {
+    let deadzones = Deadzones {
+        stick: 0.2,
+        trigger: 0.1,
+    };
+    assert_eq!(deadzones.apply_to_stick(0.1, 0.1), (0.0, 0.0));
+    assert_eq!(deadzones.apply_to_stick(1.0, 0.0), (1.0, 0.0));
+    let (x, y) = deadzones.apply_to_stick(0.6, 0.0);
+    assert!((x - 0.5).abs() < 1e-6);
+    assert_eq!(y, 0.0);
+}
+
+#[test]
+fn trigger_is_normalized_before_deadzone() {
+    let deadzones = Deadzones {
+        stick: 0.2,
+        trigger: 0.1,
+    };
+    assert_eq!(deadzones.apply_to_trigger(-1.0), 0.0);
+    assert_eq!(deadzones.apply_to_trigger(-0.85), 0.0);
+    assert_eq!(deadzones.apply_to_trigger(1.0), 1.0);
+}
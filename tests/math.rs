@@ -0,0 +1,79 @@
+use game::math::{Camera2D, Camera3D, Quat, Transform, Vec2, Vec3, Vec4};
+use std::f32::consts::FRAC_PI_2;
+
+fn assert_near(actual: Vec3, expected: Vec3) {
+    assert!(
+        (actual - expected).abs().max_element() < 1e-4,
+        "{:?} != {:?}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn transform_applies_scale_rotation_translation() {
+    let transform = Transform {
+        translation: Vec3::new(1.0, 2.0, 3.0),
+        rotation: Quat::from_rotation_z(FRAC_PI_2),
+        scale: Vec3::splat(2.0),
+    };
+    let point = Vec3::new(1.0, 0.0, 0.0);
+    let expected = Vec3::new(1.0, 4.0, 3.0);
+    assert_near(transform.transform_point(point), expected);
+    assert_near(transform.to_matrix().transform_point3(point), expected);
+    let round_trip = Transform::from_matrix(transform.to_matrix());
+    assert_near(round_trip.translation, transform.translation);
+    assert_near(round_trip.scale, transform.scale);
+}
+
+#[test]
+fn look_at_handles_direction_parallel_to_up() {
+    for target in [Vec3::new(0.0, 10.0, 0.0), Vec3::new(0.0, -10.0, 0.0)].iter() {
+        let mut transform = Transform::default();
+        transform.look_at(*target, Vec3::Y);
+        assert!(transform.rotation.is_finite());
+        assert_near(transform.forward(), target.normalize());
+    }
+    // 同じ位置を向かせても回転は変わらない
+    let mut transform = Transform::default();
+    transform.look_at(Vec3::ZERO, Vec3::Y);
+    assert_eq!(transform.rotation, Quat::IDENTITY);
+}
+
+#[test]
+fn camera_2d_maps_viewport_corners_to_clip_space() {
+    let camera = Camera2D::new(800, 600);
+    let view_projection = camera.view_projection();
+    // 左上が (-1, -1)、右下が (1, 1)
+    assert_near(
+        view_projection.transform_point3(Vec3::new(0.0, 0.0, 0.5)),
+        Vec3::new(-1.0, -1.0, 0.5),
+    );
+    assert_near(
+        view_projection.transform_point3(Vec3::new(800.0, 600.0, 0.5)),
+        Vec3::new(1.0, 1.0, 0.5),
+    );
+    let world = camera.screen_to_world(Vec2::new(200.0, 150.0));
+    assert!((world - Vec2::new(200.0, 150.0)).abs().max_element() < 1e-3);
+}
+
+#[test]
+fn camera_3d_uses_vulkan_clip_space() {
+    let mut camera = Camera3D::new(FRAC_PI_2, 100, 100);
+    camera.transform.translation = Vec3::new(0.0, 0.0, 5.0);
+    camera.look_at(Vec3::ZERO);
+    let view_projection = camera.view_projection();
+
+    let project = |point: Vec3| {
+        let clip = view_projection * point.extend(1.0);
+        clip.truncate() / clip.w
+    };
+    // near は深度 0、far は深度 1
+    assert!(project(Vec3::new(0.0, 0.0, 5.0 - camera.near)).z.abs() < 1e-4);
+    assert!((project(Vec3::new(0.0, 0.0, 5.0 - camera.far)).z - 1.0).abs() < 1e-3);
+    // ワールドの上は画面の上 (クリップ空間の -Y)
+    assert!(project(Vec3::new(0.0, 1.0, 0.0)).y < 0.0);
+    assert!(project(Vec3::new(1.0, 0.0, 0.0)).x > 0.0);
+    let clip = view_projection * Vec4::new(0.0, 0.0, 0.0, 1.0);
+    assert!(clip.w > 0.0);
+}
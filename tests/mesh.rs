@@ -0,0 +1,66 @@
+use game::mesh::{self, MeshVertex};
+use std::path::{Path, PathBuf};
+
+fn asset(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/assets")
+        .join(name)
+}
+
+#[test]
+fn obj_objects_become_separate_meshes() {
+    let meshes = mesh::load(asset("two_objects.obj")).unwrap();
+    assert_eq!(meshes.len(), 2);
+
+    let triangle = &meshes[0];
+    assert_eq!(triangle.name, "Triangle");
+    assert_eq!(triangle.indices, vec![0, 1, 2]);
+    assert_eq!(triangle.vertices[1].position, [1.0, 0.0, 0.0]);
+    assert_eq!(triangle.vertices[1].normal, [0.0, 0.0, 1.0]);
+    // OBJ の V 座標は上下を反転する
+    assert_eq!(triangle.vertices[2].uv, [0.0, 0.0]);
+    assert_eq!(triangle.vertices[0].uv, [0.0, 1.0]);
+
+    let quad = &meshes[1];
+    assert_eq!(quad.name, "Quad");
+    assert_eq!(quad.vertices.len(), 4);
+    assert_eq!(quad.indices.len(), 6);
+    assert_eq!(quad.vertices[0].color, MeshVertex::default().color);
+}
+
+#[test]
+fn gltf_primitives_become_separate_meshes() {
+    let meshes = mesh::load(asset("two_primitives.gltf")).unwrap();
+    assert_eq!(meshes.len(), 2);
+
+    let indexed = &meshes[0];
+    assert_eq!(indexed.name, "Quad#0");
+    assert_eq!(indexed.vertices.len(), 4);
+    assert_eq!(indexed.indices, vec![0, 1, 2, 0, 2, 3]);
+
+    // インデックスが無ければ頂点の順に並べる
+    let colored = &meshes[1];
+    assert_eq!(colored.name, "Quad#1");
+    assert_eq!(colored.indices, vec![0, 1, 2]);
+    assert_eq!(colored.vertices[2].color, [0.0, 0.0, 1.0, 0.5]);
+    assert_eq!(colored.vertices[2].position, [0.0, 1.0, 1.0]);
+}
+
+#[test]
+fn merged_submeshes_point_into_shared_buffers() {
+    let meshes = mesh::load(asset("two_primitives.gltf")).unwrap();
+    let (vertices, indices, submeshes) = mesh::merge(&meshes);
+    assert_eq!(vertices.len(), 7);
+    assert_eq!(indices.len(), 9);
+    assert_eq!(submeshes[1].first_index, 6);
+    assert_eq!(submeshes[1].index_count, 3);
+    assert_eq!(submeshes[1].vertex_offset, 4);
+}
+
+#[test]
+fn errors_name_the_file() {
+    let err = mesh::load(asset("missing.obj")).unwrap_err();
+    assert!(format!("{:#}", err).contains("missing.obj"));
+    let err = mesh::load(asset("mesh.fbx")).unwrap_err();
+    assert!(format!("{:#}", err).contains("Unsupported mesh format"));
+}
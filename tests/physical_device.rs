@@ -0,0 +1,62 @@
+use ash::vk::PhysicalDeviceType;
+use game::physical_device::{DeviceScore, DeviceSelection};
+
+#[test]
+fn selection_is_parsed_as_index_or_name() {
+    assert_eq!(DeviceSelection::parse("1"), DeviceSelection::Index(1));
+    assert_eq!(
+        DeviceSelection::parse(" GeForce "),
+        DeviceSelection::Name("GeForce".to_owned())
+    );
+    assert_eq!(DeviceSelection::parse(""), DeviceSelection::Auto);
+}
+
+#[test]
+fn device_type_outranks_memory_size() {
+    let discrete = DeviceScore {
+        type_rank: DeviceScore::type_rank(PhysicalDeviceType::DISCRETE_GPU),
+        optional_features: 0,
+        device_local_memory: 1 << 30,
+    };
+    let integrated = DeviceScore {
+        type_rank: DeviceScore::type_rank(PhysicalDeviceType::INTEGRATED_GPU),
+        optional_features: 2,
+        device_local_memory: 16 << 30,
+    };
+    let cpu = DeviceScore {
+        type_rank: DeviceScore::type_rank(PhysicalDeviceType::CPU),
+        optional_features: 2,
+        device_local_memory: 64 << 30,
+    };
+    assert!(discrete > integrated);
+    assert!(integrated > cpu);
+}
+
+#[test]
+fn larger_memory_wins_between_same_device_types() {
+    let small = DeviceScore {
+        type_rank: DeviceScore::type_rank(PhysicalDeviceType::DISCRETE_GPU),
+        optional_features: 2,
+        device_local_memory: 4 << 30,
+    };
+    let large = DeviceScore {
+        device_local_memory: 8 << 30,
+        ..small
+    };
+    assert!(large > small);
+}
+
+#[test]
+fn memory_size_outranks_optional_features() {
+    let more_memory = DeviceScore {
+        type_rank: DeviceScore::type_rank(PhysicalDeviceType::DISCRETE_GPU),
+        device_local_memory: 8 << 30,
+        optional_features: 0,
+    };
+    let more_features = DeviceScore {
+        device_local_memory: 4 << 30,
+        optional_features: 2,
+        ..more_memory
+    };
+    assert!(more_memory > more_features);
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use ash::vk::{
+    CullModeFlags, DynamicState, FrontFace, PolygonMode, PrimitiveTopology, SampleCountFlags,
+    ShaderStageFlags,
+};
+use bytemuck::{Pod, Zeroable};
+use common::with_headless_instance;
+use game::{
+    pipeline::{
+        alpha_blend_attachment, push_constant_range, validate_push_constants, PipelineBuilder,
+        ShaderStage,
+    },
+    shader::ShaderLibrary,
+    vertex::ColorVertex2D,
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Transform {
+    matrix: [[f32; 4]; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Tint {
+    color: [f32; 4],
+}
+
+#[test]
+fn push_constant_range_uses_size_of_type() {
+    let range = push_constant_range::<Transform>(ShaderStageFlags::VERTEX, 0);
+    assert_eq!(range.offset, 0);
+    assert_eq!(range.size, 64);
+    assert_eq!(range.stage_flags, ShaderStageFlags::VERTEX);
+}
+
+#[test]
+fn accepts_updates_inside_declared_ranges() {
+    let ranges = [
+        push_constant_range::<Transform>(ShaderStageFlags::VERTEX, 0),
+        push_constant_range::<Tint>(ShaderStageFlags::FRAGMENT, 64),
+    ];
+    assert!(validate_push_constants(&ranges, ShaderStageFlags::VERTEX, 0, 64).is_ok());
+    assert!(validate_push_constants(&ranges, ShaderStageFlags::FRAGMENT, 64, 16).is_ok());
+    assert!(validate_push_constants(&ranges, ShaderStageFlags::VERTEX, 16, 16).is_ok());
+}
+
+#[test]
+fn rejects_wrong_stage_or_range() {
+    let ranges = [
+        push_constant_range::<Transform>(ShaderStageFlags::VERTEX, 0),
+        push_constant_range::<Tint>(ShaderStageFlags::FRAGMENT, 64),
+    ];
+    // 宣言されていないステージ
+    assert!(validate_push_constants(&ranges, ShaderStageFlags::FRAGMENT, 0, 64).is_err());
+    // 範囲外
+    assert!(validate_push_constants(&ranges, ShaderStageFlags::FRAGMENT, 64, 32).is_err());
+    // 2 つの範囲にまたがるのに、片方のステージしか指定していない
+    assert!(validate_push_constants(&ranges, ShaderStageFlags::VERTEX, 48, 32).is_err());
+    // 4 の倍数でない
+    assert!(validate_push_constants(&ranges, ShaderStageFlags::VERTEX, 2, 4).is_err());
+    assert!(validate_push_constants(&ranges, ShaderStageFlags::VERTEX, 0, 0).is_err());
+    // 終端が u32 に収まらない
+    assert!(validate_push_constants(&ranges, ShaderStageFlags::VERTEX, u32::MAX - 3, 8).is_err());
+}
+
+#[test]
+fn shared_range_requires_all_stages() {
+    let ranges = [push_constant_range::<Tint>(
+        ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT,
+        0,
+    )];
+    assert!(validate_push_constants(&ranges, ShaderStageFlags::VERTEX, 0, 16).is_err());
+    assert!(validate_push_constants(
+        &ranges,
+        ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT,
+        0,
+        16
+    )
+    .is_ok());
+}
+
+fn load_stage(stage: ShaderStageFlags, name: &str) -> anyhow::Result<ShaderStage> {
+    ShaderLibrary::new("shaders").load(name, stage)
+}
+
+#[test]
+fn builder_creates_pipelines_sharing_a_render_pass() {
+    with_headless_instance(|instance| {
+        let logical_device = instance.create_logical_device(None)?;
+        let render_pass = logical_device.create_render_pass()?;
+        let vertex = load_stage(ShaderStageFlags::VERTEX, "vert.spv")?;
+        let fragment = load_stage(ShaderStageFlags::FRAGMENT, "frag.spv")?;
+        let builder = PipelineBuilder::new()
+            .stage(vertex)
+            .stage(fragment)
+            .vertex_input::<ColorVertex2D>();
+        let _triangles = builder.build(&render_pass)?;
+        let _lines = builder
+            .clone()
+            .topology(PrimitiveTopology::LINE_STRIP)
+            .cull_mode(CullModeFlags::NONE)
+            .front_face(FrontFace::COUNTER_CLOCKWISE)
+            .blend_attachments(vec![alpha_blend_attachment()])
+            .build(&render_pass)?;
+        Ok(())
+    })
+    .expect("Failed to create pipelines");
+}
+
+#[test]
+fn builder_requires_vertex_shader() {
+    with_headless_instance(|instance| {
+        let logical_device = instance.create_logical_device(None)?;
+        let render_pass = logical_device.create_render_pass()?;
+        let fragment = load_stage(ShaderStageFlags::FRAGMENT, "frag.spv")?;
+        let result = PipelineBuilder::new()
+            .stage(fragment)
+            .vertex_input::<ColorVertex2D>()
+            .build(&render_pass);
+        assert!(result.is_err());
+        Ok(())
+    })
+    .expect("Failed to create render pass");
+}
+
+#[test]
+fn builder_rejects_settings_the_device_or_render_pass_cannot_use() {
+    with_headless_instance(|instance| {
+        let logical_device = instance.create_logical_device(None)?;
+        let render_pass = logical_device.create_render_pass()?;
+        let features = *logical_device.get_enabled_features();
+        let vertex = load_stage(ShaderStageFlags::VERTEX, "vert.spv")?;
+        let builder = PipelineBuilder::new()
+            .stage(vertex)
+            .vertex_input::<ColorVertex2D>();
+        let wireframe = builder.clone().polygon_mode(PolygonMode::LINE);
+        assert_eq!(
+            wireframe.build(&render_pass).is_ok(),
+            features.fill_mode_non_solid != 0
+        );
+        let wide_lines = builder
+            .clone()
+            .topology(PrimitiveTopology::LINE_LIST)
+            .line_width(2.0);
+        assert_eq!(
+            wide_lines.build(&render_pass).is_ok(),
+            features.wide_lines != 0
+        );
+        // レンダーパスのカラーアタッチメントは 1 つ、サンプル数は 1、サブパスは 1 つ
+        assert!(builder
+            .clone()
+            .blend_attachments(vec![alpha_blend_attachment(); 2])
+            .build(&render_pass)
+            .is_err());
+        assert!(builder
+            .clone()
+            .samples(SampleCountFlags::TYPE_4)
+            .build(&render_pass)
+            .is_err());
+        assert!(builder.clone().subpass(1).build(&render_pass).is_err());
+        Ok(())
+    })
+    .expect("Failed to create render pass");
+}
+
+#[test]
+fn viewport_and_scissor_are_dynamic_unless_fixed() {
+    with_headless_instance(|instance| {
+        let logical_device = instance.create_logical_device(None)?;
+        let render_pass = logical_device.create_render_pass()?;
+        let vertex = load_stage(ShaderStageFlags::VERTEX, "vert.spv")?;
+        let fragment = load_stage(ShaderStageFlags::FRAGMENT, "frag.spv")?;
+        let builder = PipelineBuilder::new()
+            .stage(vertex)
+            .stage(fragment)
+            .vertex_input::<ColorVertex2D>()
+            .dynamic_state(DynamicState::BLEND_CONSTANTS);
+        let dynamic = builder.build(&render_pass)?;
+        assert!(dynamic.has_dynamic_state(DynamicState::VIEWPORT));
+        assert!(dynamic.has_dynamic_state(DynamicState::SCISSOR));
+        assert!(dynamic.has_dynamic_state(DynamicState::BLEND_CONSTANTS));
+        assert!(!dynamic.has_dynamic_state(DynamicState::LINE_WIDTH));
+        let fixed = builder.fixed_viewport(64, 64).build(&render_pass)?;
+        assert!(!fixed.has_dynamic_state(DynamicState::VIEWPORT));
+        assert!(!fixed.has_dynamic_state(DynamicState::SCISSOR));
+        Ok(())
+    })
+    .expect("Failed to create pipelines");
+}
@@ -0,0 +1,97 @@
+use ash::Entry;
+use game::{
+    instance::ManagedInstance, pipeline_cache::PipelineCacheHeader, shader::ShaderLibrary,
+    vertex::ColorVertex2D,
+};
+use std::fs;
+
+fn header_bytes(vendor_id: u32, device_id: u32, uuid: [u8; 16]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&32u32.to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&vendor_id.to_le_bytes());
+    data.extend_from_slice(&device_id.to_le_bytes());
+    data.extend_from_slice(&uuid);
+    data
+}
+
+#[test]
+fn parses_header_version_one() {
+    let mut data = header_bytes(0x10de, 0x1234, [7; 16]);
+    data.extend_from_slice(&[0xff; 64]);
+    assert_eq!(
+        PipelineCacheHeader::parse(&data),
+        Some(PipelineCacheHeader {
+            vendor_id: 0x10de,
+            device_id: 0x1234,
+            cache_uuid: [7; 16],
+        })
+    );
+}
+
+#[test]
+fn rejects_short_or_unknown_headers() {
+    let data = header_bytes(0x10de, 0x1234, [7; 16]);
+    assert!(PipelineCacheHeader::parse(&data[..31]).is_none());
+    assert!(PipelineCacheHeader::parse(&[]).is_none());
+    let mut unknown_version = data.clone();
+    unknown_version[4] = 2;
+    assert!(PipelineCacheHeader::parse(&unknown_version).is_none());
+    let mut too_long = data;
+    too_long[0] = 64;
+    assert!(PipelineCacheHeader::parse(&too_long).is_none());
+}
+
+/// 論理デバイスを破棄するとファイルに書き出され、次に作るときに読み込まれる。
+/// 別のデバイスのもの (UUID が違うもの) は読み込まれない
+#[test]
+fn cache_is_written_back_on_shutdown() {
+    let path = std::env::temp_dir().join(format!("game-pipeline-cache-{}.bin", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let entry = unsafe { Entry::new() }.expect("Failed to load Vulkan");
+    let mut instance = ManagedInstance::new(&entry, None, cfg!(feature = "validation_layers"))
+        .expect("Failed to create instance");
+    instance.set_panic_on_validation_error(true);
+    instance.set_pipeline_cache_path(Some(path.clone()));
+    // 作成直後のキャッシュの大きさ
+    let initial_cache_size = || {
+        let logical_device = instance
+            .create_logical_device(None)
+            .expect("Failed to create logical device");
+        let data = logical_device
+            .get_pipeline_cache()
+            .get_data()
+            .expect("Failed to get pipeline cache data");
+        data.len()
+    };
+
+    {
+        let logical_device = instance
+            .create_logical_device(None)
+            .expect("Failed to create logical device");
+        let render_pass = logical_device
+            .create_render_pass()
+            .expect("Failed to create render pass");
+        render_pass
+            .create_graphics_pipeline::<ColorVertex2D>(&ShaderLibrary::new("shaders"), &[], &[])
+            .expect("Failed to create pipeline");
+    }
+    let written = fs::read(&path).expect("Pipeline cache was not written");
+    let header = PipelineCacheHeader::parse(&written).expect("Pipeline cache has no header");
+
+    // 書き出したキャッシュが読み込まれる
+    let loaded_size = initial_cache_size();
+    assert!(
+        loaded_size > 32,
+        "Written pipeline cache ({} bytes) was not loaded",
+        written.len()
+    );
+
+    // UUID が違うキャッシュは捨てて、空のキャッシュから始める
+    let mut tampered = written;
+    tampered[16] ^= 0xff;
+    assert_ne!(PipelineCacheHeader::parse(&tampered), Some(header));
+    fs::write(&path, &tampered).expect("Failed to tamper pipeline cache");
+    assert!(initial_cache_size() < loaded_size);
+    let _ = fs::remove_file(&path);
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use ash::vk::ShaderStageFlags;
+use common::with_headless_instance;
+use game::{
+    pipeline::{PipelineBuilder, ReloadablePipeline},
+    shader::{ShaderLibrary, DEFAULT_FRAGMENT_SHADER, DEFAULT_VERTEX_SHADER},
+    vertex::ColorVertex2D,
+};
+use std::{
+    fs,
+    path::PathBuf,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// テストごとに別のディレクトリへ組み込みのシェーダをコピーする
+fn copy_shaders(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("game-shader-test-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for shader in &[DEFAULT_VERTEX_SHADER, DEFAULT_FRAGMENT_SHADER] {
+        fs::copy(PathBuf::from("shaders").join(shader), dir.join(shader)).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn library_loads_shaders_by_name() {
+    let library = ShaderLibrary::new("shaders");
+    let stage = library
+        .load(DEFAULT_VERTEX_SHADER, ShaderStageFlags::VERTEX)
+        .unwrap();
+    assert_eq!(stage.stage, ShaderStageFlags::VERTEX);
+    assert!(!stage.code.is_empty());
+}
+
+#[test]
+fn library_rejects_missing_and_invalid_shaders() {
+    let dir = copy_shaders("invalid");
+    fs::write(dir.join("broken.spv"), b"not spir-v").unwrap();
+    let library = ShaderLibrary::new(&dir);
+    assert!(library
+        .load("missing.spv", ShaderStageFlags::VERTEX)
+        .is_err());
+    assert!(library
+        .load("broken.spv", ShaderStageFlags::VERTEX)
+        .is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn poll_changes_is_empty_without_watching() {
+    let mut library = ShaderLibrary::new("shaders");
+    assert!(library.poll_changes().is_empty());
+}
+
+#[test]
+fn watch_reports_rewritten_spv_files_only() {
+    let dir = copy_shaders("watch");
+    let mut library = ShaderLibrary::new(&dir);
+    library.watch().unwrap();
+    fs::write(dir.join("notes.txt"), b"not a shader").unwrap();
+    let code = fs::read(dir.join(DEFAULT_VERTEX_SHADER)).unwrap();
+    fs::write(dir.join(DEFAULT_VERTEX_SHADER), &code).unwrap();
+
+    // 変更の通知は遅れて届くので、しばらく待つ
+    let mut changed = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while changed.is_empty() && Instant::now() < deadline {
+        changed.extend(library.poll_changes());
+        sleep(Duration::from_millis(50));
+    }
+    // 遅れて届く通知があっても `.spv` 以外は無視される
+    sleep(Duration::from_millis(500));
+    changed.extend(library.poll_changes());
+    assert_eq!(changed, vec![DEFAULT_VERTEX_SHADER.to_owned()]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_reload_keeps_previous_pipeline() {
+    let dir = copy_shaders("reload");
+    with_headless_instance(|instance| {
+        let logical_device = instance.create_logical_device(None)?;
+        let render_pass = logical_device.create_render_pass()?;
+        let library = ShaderLibrary::new(&dir);
+        let mut pipeline = ReloadablePipeline::new(
+            &library,
+            PipelineBuilder::new().vertex_input::<ColorVertex2D>(),
+            &[
+                (ShaderStageFlags::VERTEX, DEFAULT_VERTEX_SHADER),
+                (ShaderStageFlags::FRAGMENT, DEFAULT_FRAGMENT_SHADER),
+            ],
+            &render_pass,
+        )?;
+        let unchanged = vec!["other.spv".to_owned()];
+        assert!(!pipeline.reload_if_changed(&library, &render_pass, &unchanged));
+        let changed = vec![DEFAULT_VERTEX_SHADER.to_owned()];
+        assert!(pipeline.reload_if_changed(&library, &render_pass, &changed));
+        let previous = pipeline.get_pipeline().get_pipeline_raw();
+        fs::write(dir.join(DEFAULT_VERTEX_SHADER), b"not spir-v")?;
+        assert!(!pipeline.reload_if_changed(&library, &render_pass, &changed));
+        assert_eq!(pipeline.get_pipeline().get_pipeline_raw(), previous);
+        Ok(())
+    })
+    .expect("Failed to reload pipeline");
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use ash::vk::BufferUsageFlags;
+use common::with_headless_instance;
+use game::{buffer::MemoryLocation, texture::SamplerOptions};
+use image::{Rgba, RgbaImage};
+
+#[test]
+fn upload_texture_from_image() {
+    let image = RgbaImage::from_fn(16, 8, |x, y| Rgba([x as u8 * 16, y as u8 * 32, 0, 255]));
+    let (width, height, pixels) = with_headless_instance(|instance| {
+        let logical_device = instance.create_logical_device(None)?;
+        let command_pool = logical_device.create_command_pool()?;
+        let texture = logical_device.create_texture(
+            &command_pool,
+            &image,
+            SamplerOptions {
+                max_anisotropy: Some(16.0),
+                ..SamplerOptions::default()
+            },
+        )?;
+        // 転送した内容をバッファへ読み戻す
+        let read_back_buffer = logical_device.create_buffer(
+            image.as_raw().len() as u64,
+            BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+        )?;
+        command_pool
+            .allocate_command_buffer()?
+            .copy_image_to_buffer(
+                &logical_device.get_graphics_queue(),
+                texture.get_image_raw(),
+                &read_back_buffer,
+                texture.get_width(),
+                texture.get_height(),
+            )?;
+        let pixels = read_back_buffer.read::<u8>(image.as_raw().len())?;
+        Ok((texture.get_width(), texture.get_height(), pixels))
+    })
+    .expect("Failed to create texture");
+    assert_eq!((width, height), (16, 8));
+    assert_eq!(pixels, image.into_raw());
+}
+
+#[test]
+fn load_texture_reports_missing_file() {
+    with_headless_instance(|instance| {
+        let logical_device = instance.create_logical_device(None)?;
+        let command_pool = logical_device.create_command_pool()?;
+        let result = logical_device.load_texture(
+            &command_pool,
+            "tests/assets/missing.png",
+            SamplerOptions::nearest(),
+        );
+        assert!(result.is_err());
+        Ok(())
+    })
+    .expect("Failed to create logical device");
+}
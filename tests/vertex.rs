@@ -0,0 +1,64 @@
+use ash::vk::{Format, VertexInputRate};
+use game::{
+    impl_vertex,
+    vertex::{ColorVertex2D, Vertex},
+};
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MeshVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    uv: [f32; 2],
+    color: [u8; 4],
+}
+
+impl_vertex!(MeshVertex {
+    position,
+    normal,
+    uv,
+    color,
+});
+
+#[test]
+fn binding_stride_is_vertex_size() {
+    let binding = MeshVertex::binding_description(1);
+    assert_eq!(binding.binding, 1);
+    assert_eq!(binding.stride, 36);
+    assert_eq!(binding.input_rate, VertexInputRate::VERTEX);
+}
+
+#[test]
+fn attributes_follow_field_order() {
+    let attributes = MeshVertex::attribute_descriptions(1);
+    let actual: Vec<_> = attributes
+        .iter()
+        .map(|attribute| {
+            (
+                attribute.binding,
+                attribute.location,
+                attribute.format,
+                attribute.offset,
+            )
+        })
+        .collect();
+    assert_eq!(
+        actual,
+        vec![
+            (1, 0, Format::R32G32B32_SFLOAT, 0),
+            (1, 1, Format::R32G32B32_SFLOAT, 12),
+            (1, 2, Format::R32G32_SFLOAT, 24),
+            (1, 3, Format::R8G8B8A8_UNORM, 32),
+        ]
+    );
+}
+
+#[test]
+fn color_vertex_matches_triangle_shader() {
+    let attributes = ColorVertex2D::attribute_descriptions(0);
+    assert_eq!(attributes.len(), 2);
+    assert_eq!(attributes[0].format, Format::R32G32_SFLOAT);
+    assert_eq!(attributes[1].format, Format::R32G32B32_SFLOAT);
+    assert_eq!(attributes[1].offset, 8);
+    assert_eq!(ColorVertex2D::binding_description(0).stride, 20);
+}